@@ -2,16 +2,23 @@ use std::time::Duration;
 
 use bevy::prelude::*;
 
+use crate::state::AppState;
+
 #[derive(Component)]
 pub struct Animated {
     pub handle: Handle<AnimationClip>,
+    // Playback speed of the clip, 1.0 being its normal speed
+    pub speed: f32,
 }
 
 pub struct AnimationPlugin;
 
 impl Plugin for AnimationPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Update, play_animation);
+        app.add_systems(
+            Update,
+            play_animation.run_if(in_state(AppState::InGame)),
+        );
     }
 }
 
@@ -29,6 +36,7 @@ fn play_animation(
                     )
                     .repeat();
             }
+            player.set_speed(animation.speed);
         }
     }
 }
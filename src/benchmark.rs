@@ -0,0 +1,104 @@
+use bevy::{app::AppExit, prelude::*};
+
+use crate::{
+    physics::{Body, PhysicsConfig, PhysicsSet},
+    player::{Player, PlayerSet},
+    state::AppState,
+};
+
+// Speed (in blocks per second) at which the player crosses the world, so the
+// benchmark also measures the chunk streaming
+const TRAVEL_SPEED: f32 = 8.0;
+
+// Resources -------------------------------------------------------------------
+
+#[derive(Resource, Default, Debug)]
+struct Benchmark {
+    // Seconds to play once the world is loaded
+    duration: f32,
+    elapsed: f32,
+    // Real duration of each frame, in seconds
+    frames: Vec<f32>,
+}
+
+// Plugin ----------------------------------------------------------------------
+
+pub struct BenchmarkPlugin {
+    pub duration: f32,
+}
+
+impl Plugin for BenchmarkPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Benchmark {
+            duration: self.duration,
+            ..default()
+        })
+        .add_systems(
+            Update,
+            (
+                // Overrides the velocity given by the (unused) controls
+                move_player.after(PlayerSet).before(PhysicsSet),
+                record_frame,
+            )
+                .run_if(in_state(AppState::InGame)),
+        );
+    }
+}
+
+// Helpers ---------------------------------------------------------------------
+
+fn print_report(frames: &[f32]) {
+    if frames.is_empty() {
+        return;
+    }
+    let mut sorted = frames.to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+
+    let total = sorted.iter().sum::<f32>().max(f32::EPSILON);
+    // Average of the slowest 1% of the frames
+    let slowest = &sorted[sorted.len() - (sorted.len() / 100).max(1)..];
+    let low = slowest.len() as f32 / slowest.iter().sum::<f32>();
+
+    println!("Benchmark results");
+    println!("  Frames:         {}", sorted.len());
+    println!("  Average FPS:    {:.1}", sorted.len() as f32 / total);
+    println!("  1% low FPS:     {:.1}", low);
+    println!(
+        "  Frame time:     {:.2} ms (min) / {:.2} ms (max)",
+        sorted[0] * 1000.0,
+        sorted[sorted.len() - 1] * 1000.0
+    );
+}
+
+// Systems ---------------------------------------------------------------------
+
+// Walks along the x axis, the physics keeps the player on the ground. It
+// jumps whenever it's stuck against a ledge.
+fn move_player(
+    config: Res<PhysicsConfig>,
+    mut last_x: Local<Option<f32>>,
+    mut player_query: Query<(&Transform, &mut Body), With<Player>>,
+) {
+    for (transform, mut body) in player_query.iter_mut() {
+        let x = transform.translation.x;
+        if body.on_ground && *last_x == Some(x) {
+            body.velocity.y = config.jump_speed();
+        }
+        *last_x = Some(x);
+        body.velocity.x = TRAVEL_SPEED;
+    }
+}
+
+fn record_frame(
+    time: Res<Time<Real>>,
+    mut benchmark: ResMut<Benchmark>,
+    mut exit_events: EventWriter<AppExit>,
+) {
+    benchmark.elapsed += time.delta_seconds();
+    benchmark.frames.push(time.delta_seconds());
+
+    if benchmark.elapsed >= benchmark.duration {
+        print_report(&benchmark.frames);
+        exit_events.send(AppExit);
+    }
+}
@@ -0,0 +1,228 @@
+use bevy::prelude::*;
+
+use crate::{
+    entities::entity::{block, vegetation, Resources as EntityResources},
+    input::{Action, Actions, InputData},
+    physics::{Body, Obstacles},
+    player::Player,
+    state::AppState,
+    world::VoxelWorld,
+};
+
+// Resources -------------------------------------------------------------------
+
+#[derive(Resource, Clone, Debug)]
+pub struct BuildingConfig {
+    // Maximum distance between the player and the targeted block
+    pub reach: f32,
+}
+
+impl Default for BuildingConfig {
+    fn default() -> Self {
+        BuildingConfig { reach: 5.0 }
+    }
+}
+
+// Defaults to the first registered block once the definitions are loaded
+#[derive(Resource, Default, Debug)]
+pub struct SelectedBlock(pub Option<block::Variant>);
+
+// Block under the cursor (when in reach) and the empty cell where a new block
+// would be placed
+#[derive(Resource, Default, Debug)]
+pub struct BlockTarget {
+    pub block: Option<IVec3>,
+    pub placement: Option<IVec3>,
+}
+
+// Plugin ----------------------------------------------------------------------
+
+pub struct BuildingPlugin;
+
+impl Plugin for BuildingPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<BuildingConfig>()
+            .init_resource::<SelectedBlock>()
+            .init_resource::<BlockTarget>()
+            .add_systems(
+                Update,
+                (
+                    select_block,
+                    update_target,
+                    harvest_plant,
+                    break_block,
+                    place_block,
+                )
+                    .chain()
+                    .run_if(in_state(AppState::InGame)),
+            );
+    }
+}
+
+// Helpers ---------------------------------------------------------------------
+
+// Removes the plants stacked from `coordinates` upwards
+fn remove_plants(
+    commands: &mut Commands,
+    voxel_world: &mut VoxelWorld,
+    vegetation_query: &Query<(Entity, &vegetation::Entity)>,
+    coordinates: IVec3,
+) {
+    let mut coordinates = coordinates;
+    while voxel_world.remove_vegetation(coordinates).is_some() {
+        for (entity, plant) in vegetation_query.iter() {
+            if plant.coordinates == coordinates {
+                commands.entity(entity).despawn_recursive();
+            }
+        }
+        coordinates += IVec3::Y;
+    }
+}
+
+// Systems ---------------------------------------------------------------------
+
+// Number keys select the block variant to be placed
+fn select_block(
+    mut selected: ResMut<SelectedBlock>,
+    resources: Res<EntityResources>,
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+) {
+    if selected.0.is_none() {
+        selected.0 = block::variants(&resources).next();
+    }
+
+    let keys = [
+        KeyCode::Digit1,
+        KeyCode::Digit2,
+        KeyCode::Digit3,
+        KeyCode::Digit4,
+        KeyCode::Digit5,
+        KeyCode::Digit6,
+        KeyCode::Digit7,
+        KeyCode::Digit8,
+        KeyCode::Digit9,
+    ];
+    for (key, variant) in keys.iter().zip(block::variants(&resources)) {
+        if keyboard_input.just_pressed(*key) {
+            selected.0 = Some(variant);
+        }
+    }
+}
+
+fn update_target(
+    mut target: ResMut<BlockTarget>,
+    config: Res<BuildingConfig>,
+    input_data: Res<InputData>,
+    voxel_world: Res<VoxelWorld>,
+    resources: Res<EntityResources>,
+    player_query: Query<(&Transform, &Body), With<Player>>,
+) {
+    *target = BlockTarget::default();
+    let Ok((player_transform, body)) = player_query.get_single() else {
+        return;
+    };
+    let player = player_transform.translation;
+
+    let Some(hit) = input_data.target else {
+        return;
+    };
+    if hit.coordinates.as_vec3().distance(player) > config.reach {
+        return;
+    }
+
+    target.block = Some(hit.coordinates);
+
+    // New blocks go on the face that was hit, but never into the player
+    // body nor into a solid plant
+    let obstacles = Obstacles {
+        voxel_world: &voxel_world,
+        resources: &resources,
+    };
+    let placement = hit.coordinates + hit.normal;
+    if hit.normal != IVec3::ZERO
+        && !obstacles.is_solid(placement)
+        && !obstacles
+            .cells(player, body.half_extents())
+            .any(|cell| cell == placement)
+    {
+        target.placement = Some(placement);
+    }
+}
+
+// Interacting with a block picks the plants growing on it, without breaking
+// the block
+fn harvest_plant(
+    mut commands: Commands,
+    mut voxel_world: ResMut<VoxelWorld>,
+    target: Res<BlockTarget>,
+    actions: Res<Actions>,
+    vegetation_query: Query<(Entity, &vegetation::Entity)>,
+) {
+    if !actions.just_pressed(Action::Interact) {
+        return;
+    }
+    let Some(coordinates) = target.block else {
+        return;
+    };
+
+    remove_plants(
+        &mut commands,
+        &mut voxel_world,
+        &vegetation_query,
+        coordinates + IVec3::Y,
+    );
+}
+
+fn break_block(
+    mut commands: Commands,
+    mut voxel_world: ResMut<VoxelWorld>,
+    target: Res<BlockTarget>,
+    actions: Res<Actions>,
+    vegetation_query: Query<(Entity, &vegetation::Entity)>,
+) {
+    if !actions.just_pressed(Action::BreakBlock) {
+        return;
+    }
+    let Some(coordinates) = target.block else {
+        return;
+    };
+
+    if voxel_world.remove(coordinates).is_none() {
+        return;
+    }
+
+    // Plants standing on the block are removed with it
+    remove_plants(
+        &mut commands,
+        &mut voxel_world,
+        &vegetation_query,
+        coordinates + IVec3::Y,
+    );
+}
+
+fn place_block(
+    mut commands: Commands,
+    mut voxel_world: ResMut<VoxelWorld>,
+    target: Res<BlockTarget>,
+    selected: Res<SelectedBlock>,
+    actions: Res<Actions>,
+    vegetation_query: Query<(Entity, &vegetation::Entity)>,
+) {
+    if !actions.just_pressed(Action::PlaceBlock) {
+        return;
+    }
+    let (Some(coordinates), Some(variant)) = (target.placement, selected.0)
+    else {
+        return;
+    };
+
+    // Placing a block over a plant replaces it
+    remove_plants(
+        &mut commands,
+        &mut voxel_world,
+        &vegetation_query,
+        coordinates,
+    );
+
+    voxel_world.set(coordinates, variant);
+}
@@ -1,23 +1,492 @@
-use bevy::{prelude::*, render::camera::ScalingMode};
+use bevy::{
+    input::mouse::{MouseScrollUnit, MouseWheel},
+    prelude::*,
+    render::camera::ScalingMode,
+    window::{CursorGrabMode, PrimaryWindow},
+};
+use std::f32::consts::{FRAC_PI_2, FRAC_PI_4, PI};
+
+use crate::{
+    input::{Action, Actions, InputData},
+    physics::PhysicsSet,
+    player::Player,
+    state::AppState,
+};
+
+// Position of the camera relative to the point it looks at, before rotating
+// it around that point
+const OFFSET: Vec3 = Vec3::new(10.0, 10.0, 10.0);
+
+// Height of the eyes of the player above its feet
+const EYE_HEIGHT: f32 = 1.6;
+
+// Angle above the horizon of the third person camera, and its distance to the
+// player for each unit of zoom
+const THIRD_PERSON_PITCH: f32 = 0.35;
+const THIRD_PERSON_DISTANCE: f32 = 3.0;
+
+// Vertical field of view of the perspective views, in radians
+const FIELD_OF_VIEW: f32 = 1.2;
+
+// Core ------------------------------------------------------------------------
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CameraView {
+    #[default]
+    Isometric,
+    // Perspective camera orbiting the player
+    ThirdPerson,
+    // Through the eyes of the player, the mouse turns it around
+    FirstPerson,
+}
+
+impl CameraView {
+    fn next(&self) -> CameraView {
+        match self {
+            CameraView::Isometric => CameraView::ThirdPerson,
+            CameraView::ThirdPerson => CameraView::FirstPerson,
+            CameraView::FirstPerson => CameraView::Isometric,
+        }
+    }
+}
+
+// Component -------------------------------------------------------------------
+
+// Where the camera looks at and how
+#[derive(Component, Debug)]
+pub struct CameraRig {
+    pub view: CameraView,
+    pub focus: Vec3,
+    // Whether the focus follows the player, the free camera is moved with
+    // the pan actions instead
+    pub following: bool,
+    // Scale of the orthographic projection (or distance of the third person
+    // camera), higher shows more of the world
+    pub zoom: f32,
+    // Rotation around the focus point, `target_yaw` is a multiple of 90° and
+    // `yaw` follows it smoothly
+    pub yaw: f32,
+    pub target_yaw: f32,
+    // Angle above the horizon of the first person view
+    pub pitch: f32,
+}
+
+impl Default for CameraRig {
+    fn default() -> Self {
+        CameraRig {
+            view: CameraView::Isometric,
+            focus: Vec3::ZERO,
+            following: true,
+            zoom: 2.0,
+            yaw: 0.0,
+            target_yaw: 0.0,
+            pitch: 0.0,
+        }
+    }
+}
+
+impl CameraRig {
+    // Horizontal directions of the screen up and right on the ground
+    pub fn ground_axes(&self) -> (Vec3, Vec3) {
+        let rotation = Quat::from_rotation_y(self.yaw);
+        let forward = rotation.mul_vec3(Vec3::new(-1.0, 0.0, -1.0)).normalize();
+        let right = rotation.mul_vec3(Vec3::new(1.0, 0.0, -1.0)).normalize();
+        (forward, right)
+    }
+}
+
+// Resources -------------------------------------------------------------------
+
+#[derive(Resource, Clone, Debug)]
+pub struct CameraConfig {
+    pub min_zoom: f32,
+    pub max_zoom: f32,
+    // Zoom change for a single step of the mouse wheel
+    pub zoom_step: f32,
+    // Blocks per second at the default zoom
+    pub pan_speed: f32,
+    // Pixels from the window border where the cursor pans the camera, 0.0
+    // disables it
+    pub edge_margin: f32,
+    // How fast the camera turns towards its target rotation
+    pub rotation_smoothing: f32,
+    // How fast the focus catches up with the player, higher is stiffer
+    pub follow_damping: f32,
+    // Blocks in front of the player where the followed focus is
+    pub look_ahead: f32,
+    // Radians turned in first person per pixel moved by the mouse, and per
+    // second with the right stick fully pushed
+    pub mouse_sensitivity: f32,
+    pub stick_sensitivity: f32,
+}
+
+impl Default for CameraConfig {
+    fn default() -> Self {
+        CameraConfig {
+            min_zoom: 0.5,
+            max_zoom: 6.0,
+            zoom_step: 0.1,
+            pan_speed: 10.0,
+            edge_margin: 8.0,
+            rotation_smoothing: 10.0,
+            follow_damping: 5.0,
+            look_ahead: 1.5,
+            mouse_sensitivity: 0.003,
+            stick_sensitivity: 3.0,
+        }
+    }
+}
+
+// Plugin ----------------------------------------------------------------------
+
+// Systems moving the camera, the ones needing its final position run after
+#[derive(SystemSet, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct CameraSet;
 
 pub struct CameraPlugin;
 
 impl Plugin for CameraPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, spawn_camera);
+        app.init_resource::<CameraConfig>()
+            .add_systems(Startup, spawn_camera)
+            .add_systems(Update, grab_cursor)
+            .add_systems(
+                Update,
+                (
+                    cycle_view,
+                    zoom_camera,
+                    toggle_follow,
+                    pan_camera,
+                    follow_player,
+                    rotate_camera,
+                    look_around,
+                    update_camera,
+                    hide_player,
+                )
+                    .chain()
+                    .in_set(CameraSet)
+                    // The player is followed where the physics moved it
+                    .after(PhysicsSet)
+                    .run_if(in_state(AppState::InGame)),
+            );
     }
 }
 
+// Helpers ---------------------------------------------------------------------
+
+// Moves `value` towards `target`, covering the same part of the distance
+// every second whatever the frame rate
+fn smooth(value: f32, target: f32, speed: f32, delta: f32) -> f32 {
+    value + (target - value) * (1.0 - (-speed * delta).exp())
+}
+
+fn orthographic(zoom: f32) -> Projection {
+    OrthographicProjection {
+        scale: zoom,
+        scaling_mode: ScalingMode::FixedVertical(5.0),
+        ..default()
+    }
+    .into()
+}
+
+// Systems ---------------------------------------------------------------------
+
 fn spawn_camera(mut commands: Commands) {
-    commands.spawn(Camera3dBundle {
-        projection: OrthographicProjection {
-            scale: 2.0,
-            scaling_mode: ScalingMode::FixedVertical(5.0),
+    let rig = CameraRig::default();
+    commands.spawn((
+        Camera3dBundle {
+            projection: orthographic(rig.zoom),
+            transform: Transform::from_translation(rig.focus + OFFSET)
+                .looking_at(rig.focus, Vec3::Y),
             ..default()
+        },
+        rig,
+    ));
+}
+
+fn cycle_view(actions: Res<Actions>, mut rig_query: Query<&mut CameraRig>) {
+    if !actions.just_pressed(Action::CycleView) {
+        return;
+    }
+    for mut rig in rig_query.iter_mut() {
+        rig.view = rig.view.next();
+        rig.pitch = 0.0;
+    }
+}
+
+fn zoom_camera(
+    mut wheel_events: EventReader<MouseWheel>,
+    config: Res<CameraConfig>,
+    mut rig_query: Query<&mut CameraRig>,
+) {
+    let steps = wheel_events
+        .read()
+        .map(|event| match event.unit {
+            MouseScrollUnit::Line => event.y,
+            // Touchpads scroll by pixels, about a hundred per step
+            MouseScrollUnit::Pixel => event.y / 100.0,
+        })
+        .sum::<f32>();
+    if steps == 0.0 {
+        return;
+    }
+
+    // Scrolling up zooms in
+    for mut rig in rig_query.iter_mut() {
+        rig.zoom = (rig.zoom * (1.0 - config.zoom_step).powf(steps))
+            .clamp(config.min_zoom, config.max_zoom);
+    }
+}
+
+fn toggle_follow(actions: Res<Actions>, mut rig_query: Query<&mut CameraRig>) {
+    if !actions.just_pressed(Action::ToggleFollow) {
+        return;
+    }
+    for mut rig in rig_query.iter_mut() {
+        rig.following = !rig.following;
+    }
+}
+
+// Moves the focus point of the free camera with the pan actions, or when the
+// cursor is close to a border of the window
+fn pan_camera(
+    time: Res<Time>,
+    actions: Res<Actions>,
+    config: Res<CameraConfig>,
+    window_query: Query<&Window, With<PrimaryWindow>>,
+    mut rig_query: Query<&mut CameraRig>,
+) {
+    if rig_query.iter().all(|rig| rig.following) {
+        return;
+    }
+
+    let mut direction = Vec2::ZERO;
+    for (action, offset) in [
+        (Action::PanUp, Vec2::Y),
+        (Action::PanDown, Vec2::NEG_Y),
+        (Action::PanLeft, Vec2::NEG_X),
+        (Action::PanRight, Vec2::X),
+    ] {
+        if actions.pressed(action) {
+            direction += offset;
         }
-        .into(),
-        transform: Transform::from_xyz(10.0, 10.0, 10.0)
-            .looking_at(Vec3::ZERO, Vec3::Y),
-        ..default()
+    }
+
+    // The cursor position is unknown while it's outside of the window
+    let cursor = window_query.get_single().ok().and_then(|window| {
+        let size = Vec2::new(window.width(), window.height());
+        Some((size, window.cursor_position()?))
     });
+    if let Some((size, cursor)) = cursor.filter(|_| config.edge_margin > 0.0) {
+        let margin = config.edge_margin;
+        if cursor.x < margin {
+            direction.x -= 1.0;
+        }
+        if cursor.x > size.x - margin {
+            direction.x += 1.0;
+        }
+        // The window y axis points down
+        if cursor.y < margin {
+            direction.y += 1.0;
+        }
+        if cursor.y > size.y - margin {
+            direction.y -= 1.0;
+        }
+    }
+
+    let direction = direction.normalize_or_zero();
+    if direction == Vec2::ZERO {
+        return;
+    }
+
+    for mut rig in rig_query.iter_mut().filter(|rig| !rig.following) {
+        // Zooming out pans faster, so the speed on the screen stays the same
+        let speed = config.pan_speed * rig.zoom / CameraRig::default().zoom;
+        let (forward, right) = rig.ground_axes();
+        rig.focus += (forward * direction.y + right * direction.x)
+            * speed
+            * time.delta_seconds();
+    }
+}
+
+// Keeps the player in view, slightly behind the point it looks at
+fn follow_player(
+    time: Res<Time>,
+    config: Res<CameraConfig>,
+    player_query: Query<(&Transform, Ref<Player>)>,
+    mut rig_query: Query<&mut CameraRig>,
+) {
+    let Ok((transform, player)) = player_query.get_single() else {
+        return;
+    };
+    let target = transform.translation
+        + transform.rotation.mul_vec3(Vec3::Z) * config.look_ahead;
+
+    for mut rig in rig_query.iter_mut().filter(|rig| rig.following) {
+        // A new player is shown right away instead of panning to it
+        if player.is_added() {
+            rig.focus = target;
+            continue;
+        }
+        let delta = time.delta_seconds();
+        let focus = rig.focus;
+        rig.focus = Vec3::new(
+            smooth(focus.x, target.x, config.follow_damping, delta),
+            smooth(focus.y, target.y, config.follow_damping, delta),
+            smooth(focus.z, target.z, config.follow_damping, delta),
+        );
+    }
+}
+
+fn rotate_camera(
+    time: Res<Time>,
+    actions: Res<Actions>,
+    config: Res<CameraConfig>,
+    mut rig_query: Query<&mut CameraRig>,
+) {
+    for mut rig in rig_query.iter_mut() {
+        if actions.just_pressed(Action::RotateLeft) {
+            rig.target_yaw -= FRAC_PI_2;
+        }
+        if actions.just_pressed(Action::RotateRight) {
+            rig.target_yaw += FRAC_PI_2;
+        }
+
+        if rig.yaw == rig.target_yaw {
+            continue;
+        }
+        let yaw = smooth(
+            rig.yaw,
+            rig.target_yaw,
+            config.rotation_smoothing,
+            time.delta_seconds(),
+        );
+        // Close enough, lands exactly on the right angle
+        rig.yaw = if (rig.target_yaw - yaw).abs() < 0.001 {
+            rig.target_yaw
+        } else {
+            yaw
+        };
+    }
+}
+
+// The mouse (or the right stick) turns the player and tilts the camera in
+// first person
+fn look_around(
+    time: Res<Time>,
+    config: Res<CameraConfig>,
+    input_data: Res<InputData>,
+    mut rig_query: Query<&mut CameraRig>,
+    mut player_query: Query<&mut Transform, With<Player>>,
+) {
+    let stick = input_data.gamepad.right_stick;
+    let turn = -input_data.mouse_motion.x * config.mouse_sensitivity
+        - stick.x * config.stick_sensitivity * time.delta_seconds();
+    let tilt = -input_data.mouse_motion.y * config.mouse_sensitivity
+        + stick.y * config.stick_sensitivity * time.delta_seconds();
+
+    for mut rig in rig_query.iter_mut() {
+        if rig.view != CameraView::FirstPerson {
+            continue;
+        }
+        rig.pitch = (rig.pitch + tilt).clamp(-1.5, 1.5);
+        for mut transform in player_query.iter_mut() {
+            transform.rotate_y(turn);
+        }
+    }
+}
+
+fn update_camera(
+    mut camera_query: Query<(&mut Transform, &mut Projection, &CameraRig)>,
+    player_query: Query<&Transform, (With<Player>, Without<CameraRig>)>,
+) {
+    for (mut transform, mut projection, rig) in camera_query.iter_mut() {
+        match rig.view {
+            CameraView::Isometric => {
+                let offset = Quat::from_rotation_y(rig.yaw).mul_vec3(OFFSET);
+                *transform = Transform::from_translation(rig.focus + offset)
+                    .looking_at(rig.focus, Vec3::Y);
+            }
+            CameraView::ThirdPerson => {
+                let target = rig.focus + Vec3::Y * EYE_HEIGHT;
+                // Seen from the same side as the isometric view
+                let direction = Quat::from_rotation_y(rig.yaw + FRAC_PI_4)
+                    * Quat::from_rotation_x(-THIRD_PERSON_PITCH);
+                let offset = direction.mul_vec3(Vec3::new(0.0, 0.0, 1.0))
+                    * rig.zoom
+                    * THIRD_PERSON_DISTANCE;
+                *transform = Transform::from_translation(target + offset)
+                    .looking_at(target, Vec3::Y);
+            }
+            CameraView::FirstPerson => {
+                if let Ok(player) = player_query.get_single() {
+                    // The camera looks along -Z, the player along +Z
+                    transform.translation =
+                        player.translation + Vec3::Y * EYE_HEIGHT;
+                    transform.rotation = player.rotation
+                        * Quat::from_rotation_y(PI)
+                        * Quat::from_rotation_x(rig.pitch);
+                }
+            }
+        }
+
+        match (projection.as_mut(), rig.view) {
+            (Projection::Orthographic(orthographic), CameraView::Isometric) => {
+                orthographic.scale = rig.zoom;
+            }
+            (_, CameraView::Isometric) => *projection = orthographic(rig.zoom),
+            (Projection::Perspective(_), _) => {}
+            (_, _) => {
+                *projection = PerspectiveProjection {
+                    fov: FIELD_OF_VIEW,
+                    ..default()
+                }
+                .into();
+            }
+        }
+    }
+}
+
+// The model would be in the way in first person
+fn hide_player(
+    rig_query: Query<&CameraRig>,
+    mut player_query: Query<&mut Visibility, With<Player>>,
+) {
+    let hidden = rig_query
+        .iter()
+        .any(|rig| rig.view == CameraView::FirstPerson);
+    let expected = if hidden {
+        Visibility::Hidden
+    } else {
+        Visibility::Inherited
+    };
+    for mut visibility in player_query.iter_mut() {
+        if *visibility != expected {
+            *visibility = expected;
+        }
+    }
+}
+
+// The cursor is hidden and held in place while looking around in first person
+fn grab_cursor(
+    state: Res<State<AppState>>,
+    rig_query: Query<&CameraRig>,
+    mut window_query: Query<&mut Window, With<PrimaryWindow>>,
+) {
+    let grab = *state.get() == AppState::InGame
+        && rig_query
+            .iter()
+            .any(|rig| rig.view == CameraView::FirstPerson);
+    let grab_mode = if grab {
+        CursorGrabMode::Locked
+    } else {
+        CursorGrabMode::None
+    };
+
+    for mut window in window_query.iter_mut() {
+        if window.cursor.grab_mode != grab_mode {
+            window.cursor.grab_mode = grab_mode;
+            window.cursor.visible = !grab;
+        }
+    }
 }
@@ -0,0 +1,239 @@
+use bevy::prelude::*;
+use std::{iter::Peekable, path::PathBuf, process};
+
+use crate::{
+    save::{SaveConfig, StartWorld},
+    settings::DisplayMode,
+    state::AppState,
+    world::WorldSeed,
+};
+
+const USAGE: &str = "\
+Usage: rust_3d [OPTIONS]
+
+Options:
+  --seed <SEED>          Start a new world with this seed (a number or any text)
+  --world <FILE>         Play the world stored in this file (created if missing)
+  --window <MODE>        Window mode: windowed, borderless or fullscreen
+  --no-debug             Hide the debug overlay
+  --headless             Run without window nor rendering
+  --benchmark [SECONDS]  Play a new world for a while (30 seconds by default),
+                         print the frame time statistics and quit
+  -h, --help             Print this help
+
+The WORLD_SEED environment variable is used when no seed is given.";
+
+// Seconds played by `--benchmark` when no duration is given
+const BENCHMARK_DURATION: f32 = 30.0;
+
+// Resources -------------------------------------------------------------------
+
+#[derive(Resource, Clone, Debug, Default)]
+pub struct Cli {
+    pub seed: Option<WorldSeed>,
+    pub world: Option<PathBuf>,
+    pub window_mode: Option<DisplayMode>,
+    pub no_debug: bool,
+    pub headless: bool,
+    // Duration (in seconds) of the benchmark run
+    pub benchmark: Option<f32>,
+}
+
+impl Cli {
+    // Parses the process arguments, printing the usage and exiting on
+    // `--help` or on invalid arguments
+    pub fn parse() -> Cli {
+        match Cli::try_parse(std::env::args().skip(1)) {
+            Ok(Some(cli)) => cli,
+            Ok(None) => {
+                println!("{}", USAGE);
+                process::exit(0);
+            }
+            Err(error) => {
+                eprintln!("error: {}\n\n{}", error, USAGE);
+                process::exit(2);
+            }
+        }
+    }
+
+    // Returns `None` when the help was requested
+    fn try_parse(
+        args: impl Iterator<Item = String>,
+    ) -> Result<Option<Cli>, String> {
+        // `--flag=value` is the same as `--flag value`
+        let mut args = args
+            .flat_map(|arg| match arg.split_once('=') {
+                Some((flag, value)) if flag.starts_with("--") => {
+                    vec![flag.to_string(), value.to_string()]
+                }
+                _ => vec![arg],
+            })
+            .peekable();
+
+        let mut cli = Cli::default();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--seed" => {
+                    cli.seed = Some(WorldSeed::parse(&value(&mut args, &arg)?))
+                }
+                "--world" => {
+                    cli.world = Some(PathBuf::from(value(&mut args, &arg)?))
+                }
+                "--window" => {
+                    cli.window_mode =
+                        Some(match value(&mut args, &arg)?.as_str() {
+                            "windowed" => DisplayMode::Windowed,
+                            "borderless" => DisplayMode::Borderless,
+                            "fullscreen" => DisplayMode::Fullscreen,
+                            mode => {
+                                return Err(format!(
+                                    "unknown window mode {mode}"
+                                ))
+                            }
+                        })
+                }
+                "--no-debug" => cli.no_debug = true,
+                "--headless" => cli.headless = true,
+                "--benchmark" => {
+                    // The duration is optional
+                    let duration = args
+                        .peek()
+                        .and_then(|value| value.parse::<f32>().ok())
+                        .filter(|duration| *duration > 0.0);
+                    if duration.is_some() {
+                        args.next();
+                    }
+                    cli.benchmark =
+                        Some(duration.unwrap_or(BENCHMARK_DURATION));
+                }
+                "-h" | "--help" => return Ok(None),
+                _ => return Err(format!("unexpected argument {arg}")),
+            }
+        }
+        Ok(Some(cli))
+    }
+
+    // Where the world is saved, benchmarks never touch the saves
+    pub fn save_config(&self) -> SaveConfig {
+        let mut config = SaveConfig::default();
+        if let Some(path) = &self.world {
+            config.path = path.clone();
+        }
+        config.enabled = self.benchmark.is_none();
+        config
+    }
+
+    // World started right away, skipping the main menu. Benchmarks use a
+    // fixed seed so their results can be compared.
+    pub fn start_world(&self) -> Option<StartWorld> {
+        if self.world.as_ref().is_some_and(|path| path.exists()) {
+            return Some(StartWorld::Load);
+        }
+        match self.seed {
+            Some(seed) => Some(StartWorld::New(seed)),
+            None if self.benchmark.is_some() => {
+                Some(StartWorld::New(WorldSeed(0)))
+            }
+            None if self.headless || self.world.is_some() => {
+                Some(StartWorld::New(WorldSeed::from_env()))
+            }
+            None => None,
+        }
+    }
+}
+
+fn value(
+    args: &mut Peekable<impl Iterator<Item = String>>,
+    flag: &str,
+) -> Result<String, String> {
+    args.next()
+        .ok_or_else(|| format!("a value is required for {flag}"))
+}
+
+// Plugin ----------------------------------------------------------------------
+
+pub struct CliPlugin;
+
+impl Plugin for CliPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Cli>()
+            .add_systems(OnEnter(AppState::MainMenu), start_world);
+    }
+}
+
+// Systems ---------------------------------------------------------------------
+
+// Only when the game starts, quitting a world to the main menu stays there
+fn start_world(
+    cli: Res<Cli>,
+    mut started: Local<bool>,
+    mut start_events: EventWriter<StartWorld>,
+) {
+    if *started {
+        return;
+    }
+    *started = true;
+    if let Some(start) = cli.start_world() {
+        start_events.send(start);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Option<Cli>, String> {
+        Cli::try_parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn flags_accept_inline_values() {
+        let cli = parse(&["--seed=42", "--world=saves/test.ron"])
+            .unwrap()
+            .unwrap();
+        assert_eq!(cli.seed, Some(WorldSeed(42)));
+        assert_eq!(cli.world, Some(PathBuf::from("saves/test.ron")));
+
+        let cli = parse(&["--window", "borderless"]).unwrap().unwrap();
+        assert_eq!(cli.window_mode, Some(DisplayMode::Borderless));
+    }
+
+    #[test]
+    fn missing_values_are_refused() {
+        assert!(parse(&["--seed"]).is_err());
+        assert!(parse(&["--headless", "--world"]).is_err());
+    }
+
+    #[test]
+    fn unknown_window_modes_are_refused() {
+        assert!(parse(&["--window", "maximized"]).is_err());
+        assert!(parse(&["--window=maximized"]).is_err());
+    }
+
+    #[test]
+    fn benchmark_duration_is_optional() {
+        let cli = parse(&["--benchmark"]).unwrap().unwrap();
+        assert_eq!(cli.benchmark, Some(BENCHMARK_DURATION));
+
+        let cli = parse(&["--benchmark", "5", "--headless"]).unwrap().unwrap();
+        assert_eq!(cli.benchmark, Some(5.0));
+        assert!(cli.headless);
+
+        // The next flag is not taken as the duration
+        let cli = parse(&["--benchmark", "--no-debug"]).unwrap().unwrap();
+        assert_eq!(cli.benchmark, Some(BENCHMARK_DURATION));
+        assert!(cli.no_debug);
+    }
+
+    #[test]
+    fn help_stops_the_parsing() {
+        assert!(parse(&["-h"]).unwrap().is_none());
+        assert!(parse(&["--seed", "1", "--help"]).unwrap().is_none());
+    }
+
+    #[test]
+    fn unexpected_arguments_are_refused() {
+        assert!(parse(&["--unknown"]).is_err());
+        assert!(parse(&["world.ron"]).is_err());
+    }
+}
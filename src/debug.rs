@@ -4,10 +4,41 @@ use bevy::{
 };
 use bevy_async_task::{AsyncTaskRunner, AsyncTaskStatus};
 
-use crate::{input::InputData, system_info::SystemInfo};
+use crate::{
+    building::BlockTarget,
+    input::{Action, Actions, InputData},
+    state::AppState,
+    system_info::SystemInfo,
+};
 
 pub struct DebugPlugin;
 
+// Text showing the debug information
+#[derive(Component)]
+struct DebugText;
+
+// Whether the debug information is shown, toggled with its action
+#[derive(Resource)]
+struct DebugOverlay {
+    visible: bool,
+}
+
+impl Default for DebugOverlay {
+    fn default() -> Self {
+        DebugOverlay { visible: true }
+    }
+}
+
+impl DebugOverlay {
+    fn visibility(&self) -> Visibility {
+        if self.visible {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        }
+    }
+}
+
 #[derive(Resource)]
 pub struct SystemInfoData {
     pub info: SystemInfo,
@@ -26,15 +57,30 @@ impl Default for SystemInfoData {
 impl Plugin for DebugPlugin {
     fn build(&self, app: &mut App) {
         app.add_plugins(FrameTimeDiagnosticsPlugin)
-            .add_systems(Update, update)
+            .add_systems(
+                Update,
+                (toggle, update).chain().run_if(in_state(AppState::InGame)),
+            )
             .add_systems(Update, refresh_system_info)
-            .add_systems(Startup, setup)
+            .add_systems(
+                OnTransition {
+                    from: AppState::Loading,
+                    to: AppState::InGame,
+                },
+                setup,
+            )
+            .add_systems(OnEnter(AppState::MainMenu), despawn)
             .init_resource::<InputData>()
-            .init_resource::<SystemInfoData>();
+            .init_resource::<SystemInfoData>()
+            .init_resource::<DebugOverlay>();
     }
 }
 
-fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
+fn setup(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    overlay: Res<DebugOverlay>,
+) {
     let font = asset_server.load("fonts/FiraCode-Retina.ttf");
     let font_size = 18.0;
     let style = TextStyle {
@@ -42,22 +88,48 @@ fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
         font_size,
         color: Color::WHITE,
     };
-    commands.spawn(
-        TextBundle::from_sections([
-            TextSection::new(
-                "Genesys Ultimate [v0.0.0] (dev)\n",
-                style.clone(),
-            ),
-            TextSection::new("Copyright 2024 GMALTEZ CORP \n", style.clone()),
-            TextSection::from_style(style.clone()),
-            TextSection::from_style(style.clone()),
-            TextSection::from_style(style.clone()),
-            TextSection::from_style(style.clone()),
-            TextSection::from_style(style.clone()),
-            TextSection::from_style(style.clone()),
-        ])
-        .with_background_color(Color::rgba(0.0, 0.0, 0.0, 0.5)),
-    );
+    commands
+        .spawn((
+            TextBundle::from_sections([
+                TextSection::new(
+                    "Genesys Ultimate [v0.0.0] (dev)\n",
+                    style.clone(),
+                ),
+                TextSection::new(
+                    "Copyright 2024 GMALTEZ CORP \n",
+                    style.clone(),
+                ),
+                TextSection::from_style(style.clone()),
+                TextSection::from_style(style.clone()),
+                TextSection::from_style(style.clone()),
+                TextSection::from_style(style.clone()),
+                TextSection::from_style(style.clone()),
+                TextSection::from_style(style.clone()),
+            ])
+            .with_background_color(Color::rgba(0.0, 0.0, 0.0, 0.5)),
+            DebugText,
+        ))
+        .insert(overlay.visibility());
+}
+
+fn despawn(mut commands: Commands, query: Query<Entity, With<DebugText>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+fn toggle(
+    actions: Res<Actions>,
+    mut overlay: ResMut<DebugOverlay>,
+    mut query: Query<&mut Visibility, With<DebugText>>,
+) {
+    if !actions.just_pressed(Action::ToggleDebug) {
+        return;
+    }
+    overlay.visible = !overlay.visible;
+    for mut visibility in query.iter_mut() {
+        *visibility = overlay.visibility();
+    }
 }
 
 fn refresh_system_info(
@@ -82,9 +154,15 @@ fn update(
     diagnostics: Res<DiagnosticsStore>,
     system_info: ResMut<SystemInfoData>,
     input_data: Res<InputData>,
+    target: Res<BlockTarget>,
+    overlay: Res<DebugOverlay>,
     mut gizmos: Gizmos,
-    mut query: Query<&mut Text>,
+    mut query: Query<&mut Text, With<DebugText>>,
 ) {
+    if !overlay.visible {
+        return;
+    }
+
     let fps = diagnostics
         .get(&FrameTimeDiagnosticsPlugin::FPS)
         .and_then(|fps| fps.average());
@@ -113,36 +191,30 @@ fn update(
             system_info.info.memory.total,
             system_info.info.memory.percentage
         );
-        text.sections[7].value = format!(
-            "\n\nBlock X: {:.2} Y: {:.2} Z: {:.2}",
-            input_data.mouse_position.coordinates.x,
-            input_data.mouse_position.coordinates.y,
-            input_data.mouse_position.coordinates.z
-        );
+        text.sections[7].value = match input_data.target {
+            Some(hit) => format!(
+                "\n\nBlock X: {} Y: {} Z: {} | Face: {} | Distance: {:.2}",
+                hit.coordinates.x,
+                hit.coordinates.y,
+                hit.coordinates.z,
+                hit.normal,
+                hit.distance
+            ),
+            None => "\n\nBlock: -".to_string(),
+        };
     }
 
-    gizmos.cuboid(
-        Transform {
-            translation: Vec3::new(
-                input_data.mouse_position.coordinates.x.floor(),
-                0.0,
-                input_data.mouse_position.coordinates.z.floor(),
-            ),
-            scale: Vec3::splat(1.0),
-            ..Default::default()
-        },
-        Color::RED,
-    );
-    gizmos.cuboid(
-        Transform {
-            translation: Vec3::new(
-                input_data.mouse_position.coordinates.x.floor(),
-                1.0,
-                input_data.mouse_position.coordinates.z.floor(),
-            ),
-            scale: Vec3::splat(1.0),
-            ..Default::default()
-        },
-        Color::BLUE,
-    );
+    // Targeted block and the cell where a new block would be placed
+    if let Some(coordinates) = target.block {
+        gizmos.cuboid(
+            Transform::from_translation(coordinates.as_vec3()),
+            Color::RED,
+        );
+    }
+    if let Some(coordinates) = target.placement {
+        gizmos.cuboid(
+            Transform::from_translation(coordinates.as_vec3()),
+            Color::BLUE,
+        );
+    }
 }
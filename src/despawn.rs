@@ -1,31 +0,0 @@
-use crate::entities::entity::{block, vegetation};
-use bevy::prelude::*;
-
-pub struct DespawnPlugin;
-
-impl Plugin for DespawnPlugin {
-    fn build(&self, app: &mut App) {
-        app.add_systems(Update, despawn);
-    }
-}
-
-fn despawn(
-    mut commands: Commands,
-    camera_query: Query<(&Camera, &GlobalTransform)>,
-    query: Query<
-        (Entity, &GlobalTransform),
-        Or<(Added<block::Entity>, Added<vegetation::Entity>)>,
-    >,
-) {
-    for (entity, transform) in query.iter() {
-        let distance = transform.translation().distance(Vec3::ZERO);
-
-        for (_, camera_transform) in camera_query.iter() {
-            let camera_distance =
-                camera_transform.translation().distance(Vec3::ZERO);
-            if distance > camera_distance {
-                commands.entity(entity).despawn_recursive();
-            }
-        }
-    }
-}
@@ -1,72 +1,99 @@
-use super::get_model as core_get_model;
+use super::get_material as core_get_material;
+use super::get_mesh as core_get_mesh;
 use super::*;
 
 // Core ------------------------------------------------------------------------
 
-#[derive(Component)]
-pub struct Entity {
-    pub coordinates: IVec3,
-    pub variant: Variant,
-}
+#[derive(
+    Component,
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    Hash,
+    PartialOrd,
+    Ord,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+#[serde(transparent)]
+pub struct Variant(pub Id);
 
-#[derive(Component, PartialEq)]
-pub enum Variant {
-    Grass,
-    Dirt,
-}
+// Blocks are not spawned one by one, their models are merged into a single
+// mesh per chunk by the world module
 
-// Helpers ---------------------------------------------------------------------
+// Definitions -----------------------------------------------------------------
 
-pub fn spawn(commands: &mut Commands, resources: &Resources, entity: Entity) {
-    commands.spawn((
-        SceneBundle {
-            scene: get_model(&entity.variant, resources),
-            transform: Transform {
-                translation: entity.coordinates.as_vec3(),
-                scale: Vec3::splat(0.5),
-                ..Default::default()
-            },
-            ..Default::default()
-        },
-        entity,
-    ));
+#[derive(Clone, Debug, serde::Deserialize)]
+pub struct Definition {
+    pub name: Variant,
+    pub mesh: String,
+    pub material: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
 }
 
-// Variants & Animations -------------------------------------------------------
-
-struct ModelData<'a> {
-    path: &'a str,
-    variant: Variant,
+impl Tagged for Definition {
+    fn tags(&self) -> &[String] {
+        &self.tags
+    }
 }
 
-const MODELS: [ModelData; 2] = [
-    ModelData {
-        path: "models/block_grass.glb#Scene0",
-        variant: Variant::Grass,
-    },
-    ModelData {
-        path: "models/block_dirt.glb#Scene0",
-        variant: Variant::Dirt,
-    },
-];
-
-pub fn get_model(variant: &Variant, resources: &Resources) -> Handle<Scene> {
-    match MODELS.iter().find(|model| model.variant == *variant) {
-        Some(model) => core_get_model(model.path.to_string(), resources),
-        None => panic!("Error while loading model"),
+pub fn register(
+    definition: Definition,
+    resources: &mut Resources,
+    asset_server: &Res<AssetServer>,
+) {
+    if get_definition(&definition.name, resources).is_some() {
+        warn!("Block {} is defined twice, ignoring", definition.name.0);
+        return;
     }
+    load_mesh(definition.mesh.clone(), resources, asset_server);
+    load_material(definition.material.clone(), resources, asset_server);
+    resources.blocks.push(definition);
 }
 
-pub fn load_assets(resources: &mut Resources, asset_server: &Res<AssetServer>) {
-    for model in MODELS.iter() {
-        load_model(model.path.to_string(), resources, asset_server);
+fn get_definition<'a>(
+    variant: &Variant,
+    resources: &'a Resources,
+) -> Option<&'a Definition> {
+    let definition = resources
+        .blocks
+        .iter()
+        .find(|definition| definition.name == *variant);
+    if definition.is_none() && resources.is_ready() {
+        warn_unknown("block", variant.0);
     }
+    definition
+}
+
+// Registered variants, in the order they were defined
+pub fn variants(resources: &Resources) -> impl Iterator<Item = Variant> + '_ {
+    resources.blocks.iter().map(|definition| definition.name)
+}
+
+pub fn get_mesh(
+    variant: &Variant,
+    resources: &Resources,
+) -> Option<Handle<Mesh>> {
+    get_definition(variant, resources).and_then(|definition| {
+        core_get_mesh(definition.mesh.clone(), resources)
+    })
+}
+
+pub fn get_material(
+    variant: &Variant,
+    resources: &Resources,
+) -> Option<Handle<StandardMaterial>> {
+    get_definition(variant, resources).and_then(|definition| {
+        core_get_material(definition.material.clone(), resources)
+    })
 }
 
 // Exportable ------------------------------------------------------------------
 
 pub mod exportable {
-    pub use super::spawn;
-    pub use super::Entity;
     pub use super::Variant;
+    pub use super::{get_material, get_mesh, variants};
 }
@@ -11,106 +11,195 @@ pub struct Entity {
     pub variant: Variant,
 }
 
-#[derive(Component, PartialEq)]
-pub enum Variant {
-    Player,
+#[derive(
+    Component,
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    Hash,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+#[serde(transparent)]
+pub struct Variant(pub Id);
+
+impl Variant {
+    pub const PLAYER: Variant = Variant::new("player");
+
+    pub const fn new(name: &'static str) -> Variant {
+        Variant(Id::new(name))
+    }
 }
 
-#[derive(Component, PartialEq)]
-pub enum Animation {
-    Idle,
-    Walk,
+#[derive(
+    Component,
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    Hash,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+#[serde(transparent)]
+pub struct Animation(pub Id);
+
+// Animations the game logic relies on, the clip of each one is picked by the
+// character definition
+impl Animation {
+    pub const IDLE: Animation = Animation::new("idle");
+    pub const WALK: Animation = Animation::new("walk");
+    pub const RUN: Animation = Animation::new("run");
+    // Optional, the walk is slowed down when it's missing
+    pub const SNEAK: Animation = Animation::new("sneak");
+
+    pub const fn new(name: &'static str) -> Animation {
+        Animation(Id::new(name))
+    }
 }
 
+// Offset of the model applied when the character was spawned, kept so it can
+// be replaced when the definition changes
+#[derive(Component, Debug)]
+pub struct ModelOffset(f32);
+
 // Helpers ---------------------------------------------------------------------
 
+// Returns `None` (nothing is spawned) when the variant is not registered
 pub fn spawn<T: Bundle>(
     commands: &mut Commands,
     resources: &Resources,
     entity: Entity,
     bundle: T,
+) -> Option<bevy::ecs::entity::Entity> {
+    let definition = get_definition(&entity.variant, resources)?;
+    let scene = core_get_model(definition.model.clone(), resources)?;
+
+    Some(
+        commands
+            .spawn((
+                SceneBundle {
+                    scene,
+                    transform: Transform {
+                        translation: entity.coordinates.as_vec3()
+                            + Vec3::Y * definition.y_offset,
+                        scale: Vec3::splat(definition.scale),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+                Animated {
+                    handle: get_animation(
+                        &entity.variant,
+                        &Animation::IDLE,
+                        resources,
+                    )
+                    .unwrap_or_default(),
+                    speed: 1.0,
+                },
+                entity.variant,
+                ModelOffset(definition.y_offset),
+                bundle,
+            ))
+            .id(),
+    )
+}
+
+// Applies the current definition of the character to an already spawned one,
+// its position is kept (only the model offset changes)
+pub fn apply(
+    variant: &Variant,
+    offset: &mut ModelOffset,
+    scene: &mut Handle<Scene>,
+    transform: &mut Transform,
+    resources: &Resources,
 ) {
-    commands.spawn((
-        SceneBundle {
-            scene: get_model(&entity.variant, resources),
-            transform: Transform {
-                translation: Vec3::new(
-                    entity.coordinates.x as f32,
-                    entity.coordinates.y as f32 + 0.5,
-                    entity.coordinates.z as f32,
-                ),
-                ..Default::default()
-            },
-            ..Default::default()
-        },
-        Animated {
-            handle: get_animation(&entity.variant, &Animation::Idle, resources),
-        },
-        bundle,
-    ));
+    let Some(definition) = get_definition(variant, resources) else {
+        return;
+    };
+    let Some(model) = core_get_model(definition.model.clone(), resources)
+    else {
+        return;
+    };
+
+    if *scene != model {
+        *scene = model;
+    }
+    transform.translation.y += definition.y_offset - offset.0;
+    transform.scale = Vec3::splat(definition.scale);
+    offset.0 = definition.y_offset;
 }
 
-// Variants & Animations -------------------------------------------------------
+// Definitions -----------------------------------------------------------------
 
-struct ModelData<'a> {
-    path: &'a str,
-    variant: Variant,
+fn default_scale() -> f32 {
+    1.0
 }
 
-struct AnimationData<'a> {
-    animation: Animation,
-    path: &'a str,
-    variant: Variant,
+#[derive(Clone, Debug, serde::Deserialize)]
+pub struct Definition {
+    pub name: Variant,
+    pub model: String,
+    #[serde(default = "default_scale")]
+    pub scale: f32,
+    // Vertical offset of the model from the center of its cell
+    #[serde(default)]
+    pub y_offset: f32,
+    // Clip played for each animation
+    #[serde(default)]
+    pub animations: HashMap<Animation, String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
 }
 
-const MODELS: [ModelData; 1] = [ModelData {
-    path: "models/player.glb#Scene0",
-    variant: Variant::Player,
-}];
-
-const ANIMATIONS: [AnimationData; 2] = [
-    AnimationData {
-        animation: Animation::Idle,
-        path: "models/player.glb#Animation3",
-        variant: Variant::Player,
-    },
-    AnimationData {
-        animation: Animation::Walk,
-        path: "models/player.glb#Animation6",
-        variant: Variant::Player,
-    },
-];
-
-pub fn get_model(variant: &Variant, resources: &Resources) -> Handle<Scene> {
-    match MODELS.iter().find(|model| model.variant == *variant) {
-        Some(model) => core_get_model(model.path.to_string(), resources),
-        None => panic!("Error while loading model"),
+impl Tagged for Definition {
+    fn tags(&self) -> &[String] {
+        &self.tags
     }
 }
 
-pub fn get_animation(
-    variant: &Variant,
-    animation: &Animation,
-    resources: &Resources,
-) -> Handle<AnimationClip> {
-    match ANIMATIONS.iter().find(|animation_data| {
-        animation_data.variant == *variant
-            && animation_data.animation == *animation
-    }) {
-        Some(animation) => {
-            core_get_animation(animation.path.to_string(), resources)
-        }
-        None => panic!("Error while loading animation"),
+pub fn register(
+    definition: Definition,
+    resources: &mut Resources,
+    asset_server: &Res<AssetServer>,
+) {
+    if get_definition(&definition.name, resources).is_some() {
+        warn!("Character {} is defined twice, ignoring", definition.name.0);
+        return;
+    }
+    load_model(definition.model.clone(), resources, asset_server);
+    for path in definition.animations.values() {
+        load_animation(path.clone(), resources, asset_server);
     }
+    resources.characters.push(definition);
 }
 
-pub fn load_assets(resources: &mut Resources, asset_server: &Res<AssetServer>) {
-    for model in MODELS.iter() {
-        load_model(model.path.to_string(), resources, asset_server);
-    }
-    for animation in ANIMATIONS.iter() {
-        load_animation(animation.path.to_string(), resources, asset_server);
+fn get_definition<'a>(
+    variant: &Variant,
+    resources: &'a Resources,
+) -> Option<&'a Definition> {
+    let definition = resources
+        .characters
+        .iter()
+        .find(|definition| definition.name == *variant);
+    if definition.is_none() && resources.is_ready() {
+        warn_unknown("character", variant.0);
     }
+    definition
+}
+
+pub fn get_animation(
+    variant: &Variant,
+    animation: &Animation,
+    resources: &Resources,
+) -> Option<Handle<AnimationClip>> {
+    get_definition(variant, resources)?
+        .animations
+        .get(animation)
+        .and_then(|path| core_get_animation(path.clone(), resources))
 }
 
 // Exportable ------------------------------------------------------------------
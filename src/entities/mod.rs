@@ -1,26 +1,219 @@
-use bevy::prelude::*;
-use rand::Rng;
-use std::collections::HashMap;
+use bevy::{
+    asset::{
+        io::Reader, AssetLoader, AsyncReadExt, LoadContext, LoadState,
+        LoadedFolder, UntypedAssetId,
+    },
+    prelude::*,
+    utils::BoxedFuture,
+};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    sync::Mutex,
+};
 
 mod block;
 mod character;
 mod vegetation;
 
+use crate::world::BiomeData;
+
 pub mod entity {
     pub use super::block::exportable as block;
     pub use super::character::exportable as character;
     pub use super::vegetation::exportable as vegetation;
-    pub use super::Resources;
+    pub use super::{EntitiesReloaded, Resources};
+}
+
+// Every `*.entities.ron` file in this folder is loaded and registered
+const DEFINITIONS_FOLDER: &str = "entities";
+
+// Identifiers -----------------------------------------------------------------
+
+// Name of an entity type (or animation) as written in the definition files.
+// Names are interned, so identifiers are `Copy` and cheap to compare.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Id(&'static str);
+
+impl Id {
+    pub const fn new(name: &'static str) -> Id {
+        Id(name)
+    }
+
+    // Every distinct name is allocated once and kept until the game exits,
+    // reloading files that use the same names allocates nothing
+    pub fn intern(name: &str) -> Id {
+        static NAMES: Mutex<Option<HashSet<&'static str>>> = Mutex::new(None);
+
+        let mut names = NAMES.lock().unwrap_or_else(|error| error.into_inner());
+        let names = names.get_or_insert_with(HashSet::new);
+        match names.get(name) {
+            Some(name) => Id(name),
+            None => {
+                let name: &'static str = Box::leak(name.into());
+                names.insert(name);
+                Id(name)
+            }
+        }
+    }
+}
+
+impl fmt::Debug for Id {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.0)
+    }
+}
+
+impl fmt::Display for Id {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Serialize for Id {
+    fn serialize<S: Serializer>(
+        &self,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for Id {
+    fn deserialize<D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Id, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        Ok(Id::intern(&name))
+    }
+}
+
+// Definitions -----------------------------------------------------------------
+
+// Content of an `*.entities.ron` file, each file may declare any kind of entity
+#[derive(Asset, TypePath, Deserialize, Default, Debug)]
+pub struct EntityDefinitions {
+    #[serde(default)]
+    pub blocks: Vec<block::Definition>,
+    #[serde(default)]
+    pub vegetation: Vec<vegetation::Definition>,
+    #[serde(default)]
+    pub characters: Vec<character::Definition>,
+    #[serde(default)]
+    pub biomes: Vec<BiomeData>,
+}
+
+#[derive(Debug)]
+pub enum DefinitionsError {
+    Io(std::io::Error),
+    Ron(ron::error::SpannedError),
+}
+
+impl fmt::Display for DefinitionsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DefinitionsError::Io(error) => write!(f, "{}", error),
+            DefinitionsError::Ron(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for DefinitionsError {}
+
+impl From<std::io::Error> for DefinitionsError {
+    fn from(error: std::io::Error) -> Self {
+        DefinitionsError::Io(error)
+    }
+}
+
+impl From<ron::error::SpannedError> for DefinitionsError {
+    fn from(error: ron::error::SpannedError) -> Self {
+        DefinitionsError::Ron(error)
+    }
 }
 
+#[derive(Default)]
+pub struct EntityDefinitionsLoader;
+
+impl AssetLoader for EntityDefinitionsLoader {
+    type Asset = EntityDefinitions;
+    type Settings = ();
+    type Error = DefinitionsError;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader,
+        _settings: &'a (),
+        _load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<EntityDefinitions, DefinitionsError>> {
+        Box::pin(async move {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes).await?;
+            Ok(ron::de::from_bytes::<EntityDefinitions>(&bytes)?)
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["entities.ron"]
+    }
+}
+
+// Events ----------------------------------------------------------------------
+
+// Sent when a definition file changed and the entity types were registered
+// again
+#[derive(Event, Clone, Debug)]
+pub struct EntitiesReloaded;
+
 // Resources -------------------------------------------------------------------
 
 #[derive(Resource, Default)]
 pub struct Resources {
+    definitions: Handle<LoadedFolder>,
+    // Entity types, registered once their definition files are loaded
+    blocks: Vec<block::Definition>,
+    vegetation: Vec<vegetation::Definition>,
+    characters: Vec<character::Definition>,
+    biomes: Vec<BiomeData>,
+    ready: bool,
+    // Set when a definition file was added, changed or removed after the
+    // registration
+    stale: bool,
     models: HashMap<String, Handle<Scene>>,
+    meshes: HashMap<String, Handle<Mesh>>,
+    materials: HashMap<String, Handle<StandardMaterial>>,
     animations: HashMap<String, Handle<AnimationClip>>,
 }
 
+impl Resources {
+    // Whether the entity types are registered (their models may still be
+    // loading)
+    pub fn is_ready(&self) -> bool {
+        self.ready
+    }
+
+    // Biomes used to generate the world, in the order they were defined
+    pub fn biomes(&self) -> &[BiomeData] {
+        &self.biomes
+    }
+
+    // Every asset requested by the registered entities, including the
+    // definition files themselves
+    pub fn assets(&self) -> Vec<UntypedAssetId> {
+        let mut assets = vec![self.definitions.id().untyped()];
+        assets.extend(self.models.values().map(|handle| handle.id().untyped()));
+        assets.extend(self.meshes.values().map(|handle| handle.id().untyped()));
+        assets.extend(
+            self.materials.values().map(|handle| handle.id().untyped()),
+        );
+        assets.extend(
+            self.animations.values().map(|handle| handle.id().untyped()),
+        );
+        assets
+    }
+}
+
 // Plugin ----------------------------------------------------------------------
 
 pub struct EntityPlugin;
@@ -28,79 +221,204 @@ pub struct EntityPlugin;
 impl Plugin for EntityPlugin {
     fn build(&self, app: &mut App) {
         app.init_resource::<Resources>()
-            .add_systems(Startup, load_assets)
-            .add_systems(PostStartup, spawn_world);
+            .init_asset::<EntityDefinitions>()
+            .init_asset_loader::<EntityDefinitionsLoader>()
+            .add_event::<EntitiesReloaded>()
+            .add_systems(Startup, load_definitions)
+            .add_systems(
+                Update,
+                (
+                    watch_definitions,
+                    register_definitions,
+                    apply_definitions,
+                    respawn_scenes,
+                )
+                    .chain(),
+            );
+    }
+}
+
+fn load_definitions(
+    mut resources: ResMut<Resources>,
+    asset_server: Res<AssetServer>,
+) {
+    resources.definitions = asset_server.load_folder(DEFINITIONS_FOLDER);
+}
+
+// Marks the registered entity types as stale when a definition file changes
+// on disk, or when files are added to (or removed from) the folder. A new
+// file is only loaded after the folder changed, the registration waits for
+// it.
+fn watch_definitions(
+    mut resources: ResMut<Resources>,
+    mut events: EventReader<AssetEvent<EntityDefinitions>>,
+    mut folder_events: EventReader<AssetEvent<LoadedFolder>>,
+) {
+    let changed = events
+        .read()
+        .filter(|event| matches!(event, AssetEvent::Modified { .. }))
+        .count()
+        > 0;
+    let folder = resources.definitions.id();
+    let folder_changed = folder_events
+        .read()
+        .filter(|event| event.is_modified(folder))
+        .count()
+        > 0;
+
+    if resources.ready && (changed || folder_changed) {
+        resources.stale = true;
     }
 }
 
-fn load_assets(
+// Registers the entity types once every definition file is loaded, and again
+// whenever they are stale
+fn register_definitions(
     mut resources: ResMut<Resources>,
+    mut reloaded: EventWriter<EntitiesReloaded>,
     asset_server: Res<AssetServer>,
+    folders: Res<Assets<LoadedFolder>>,
+    definitions: Res<Assets<EntityDefinitions>>,
+) {
+    let reloading = resources.ready;
+    if reloading && !resources.stale {
+        return;
+    }
+    let Some(folder) = folders.get(&resources.definitions) else {
+        return;
+    };
+
+    // Wait for every definition file of the folder
+    let mut files = Vec::new();
+    for handle in folder.handles.iter() {
+        let Ok(handle) = handle.clone().try_typed::<EntityDefinitions>() else {
+            continue;
+        };
+        // Files that failed to load are reported by the loading screen and
+        // skipped here
+        if asset_server.load_state(&handle) == LoadState::Failed {
+            continue;
+        }
+        match definitions.get(&handle) {
+            Some(file) => files.push(file),
+            None => return,
+        }
+    }
+
+    resources.ready = false;
+    resources.blocks.clear();
+    resources.vegetation.clear();
+    resources.characters.clear();
+    resources.biomes.clear();
+
+    // The assets are requested again by the definitions, so that the ones no
+    // longer used are dropped. The old handles are kept until then, which
+    // keeps the assets still in use loaded.
+    let _previous = (
+        std::mem::take(&mut resources.models),
+        std::mem::take(&mut resources.meshes),
+        std::mem::take(&mut resources.materials),
+        std::mem::take(&mut resources.animations),
+    );
+
+    for file in files {
+        for definition in file.blocks.iter() {
+            block::register(definition.clone(), &mut resources, &asset_server);
+        }
+        for definition in file.vegetation.iter() {
+            vegetation::register(
+                definition.clone(),
+                &mut resources,
+                &asset_server,
+            );
+        }
+        for definition in file.characters.iter() {
+            character::register(
+                definition.clone(),
+                &mut resources,
+                &asset_server,
+            );
+        }
+        for biome in file.biomes.iter() {
+            if resources
+                .biomes
+                .iter()
+                .any(|other| other.name == biome.name)
+            {
+                warn!("Biome {} is defined twice, ignoring", biome.name);
+                continue;
+            }
+            resources.biomes.push(biome.clone());
+        }
+    }
+
+    info!(
+        "Registered {} blocks, {} plants, {} characters and {} biomes",
+        resources.blocks.len(),
+        resources.vegetation.len(),
+        resources.characters.len(),
+        resources.biomes.len()
+    );
+    resources.ready = true;
+    resources.stale = false;
+
+    if reloading {
+        reloaded.send(EntitiesReloaded);
+    }
+}
+
+// Updates the models and transforms of the spawned entities after a reload
+fn apply_definitions(
+    resources: Res<Resources>,
+    mut events: EventReader<EntitiesReloaded>,
+    mut vegetation_query: Query<(
+        &vegetation::Entity,
+        &mut Handle<Scene>,
+        &mut Transform,
+    )>,
+    mut character_query: Query<
+        (
+            &character::Variant,
+            &mut character::ModelOffset,
+            &mut Handle<Scene>,
+            &mut Transform,
+        ),
+        Without<vegetation::Entity>,
+    >,
+) {
+    if events.read().last().is_none() {
+        return;
+    }
+
+    for (entity, mut scene, mut transform) in vegetation_query.iter_mut() {
+        vegetation::apply(entity, &mut scene, &mut transform, &resources);
+    }
+    for (variant, mut offset, mut scene, mut transform) in
+        character_query.iter_mut()
+    {
+        character::apply(
+            variant,
+            &mut offset,
+            &mut scene,
+            &mut transform,
+            &resources,
+        );
+    }
+}
+
+// Scenes of a glTF file that changed on disk are reloaded under the same
+// handles, the entities using them have to be spawned again
+fn respawn_scenes(
+    mut events: EventReader<AssetEvent<Scene>>,
+    mut scene_query: Query<&mut Handle<Scene>>,
 ) {
-    block::load_assets(&mut resources, &asset_server);
-    character::load_assets(&mut resources, &asset_server);
-    vegetation::load_assets(&mut resources, &asset_server);
-}
-
-fn spawn_world(mut commands: Commands, resources: Res<Resources>) {
-    for x in -30..30 {
-        for z in -30..30 {
-            if rand::thread_rng().gen_bool(0.3) {
-                entity::block::spawn(
-                    &mut commands,
-                    &resources,
-                    entity::block::Entity {
-                        coordinates: IVec3 { x, y: 0, z },
-                        variant: entity::block::Variant::Dirt,
-                    },
-                );
-                if rand::thread_rng().gen_bool(0.5) {
-                    entity::vegetation::spawn(
-                        &mut commands,
-                        &resources,
-                        entity::vegetation::Entity {
-                            coordinates: IVec3 { x, y: 1, z },
-                            variant: entity::vegetation::Variant::Bamboo,
-                        },
-                    );
-                    if rand::thread_rng().gen_bool(0.5) {
-                        entity::vegetation::spawn(
-                            &mut commands,
-                            &resources,
-                            entity::vegetation::Entity {
-                                coordinates: IVec3 { x, y: 2, z },
-                                variant: entity::vegetation::Variant::Bamboo,
-                            },
-                        );
-                    }
-                } else if rand::thread_rng().gen_bool(0.01) {
-                    entity::vegetation::spawn(
-                        &mut commands,
-                        &resources,
-                        entity::vegetation::Entity {
-                            coordinates: IVec3 { x, y: 1, z },
-                            variant: entity::vegetation::Variant::Tree,
-                        },
-                    );
-                } else {
-                    // entity::vegetation::spawn(
-                    //     &mut commands,
-                    //     &resources,
-                    //     entity::vegetation::Entity {
-                    //         coordinates: IVec3 { x, y: 1, z },
-                    //         variant: entity::vegetation::Variant::Grass,
-                    //     },
-                    // );
-                }
-            } else {
-                entity::block::spawn(
-                    &mut commands,
-                    &resources,
-                    entity::block::Entity {
-                        coordinates: IVec3 { x, y: 0, z },
-                        variant: entity::block::Variant::Grass,
-                    },
-                );
+    for event in events.read() {
+        let AssetEvent::Modified { id } = event else {
+            continue;
+        };
+        for mut scene in scene_query.iter_mut() {
+            if scene.id() == *id {
+                scene.set_changed();
             }
         }
     }
@@ -108,42 +426,119 @@ fn spawn_world(mut commands: Commands, resources: Res<Resources>) {
 
 // Entity Helpers --------------------------------------------------------------
 
-fn get_model(path: String, resources: &Resources) -> Handle<Scene> {
-    match resources.models.get(&path) {
-        Some(model) => model.clone(),
-        None => panic!("Model not loaded: {}", path),
+// Unknown names may be looked up on every frame (e.g. by the physics), each
+// one is only reported once
+fn warn_unknown(kind: &'static str, id: Id) {
+    static REPORTED: Mutex<Option<HashSet<(&'static str, Id)>>> =
+        Mutex::new(None);
+
+    let mut reported =
+        REPORTED.lock().unwrap_or_else(|error| error.into_inner());
+    if reported.get_or_insert_with(HashSet::new).insert((kind, id)) {
+        warn!("Unknown {}: {}", kind, id);
+    }
+}
+
+// Free-form labels of an entity type, given a meaning by the systems checking
+// them (e.g. "solid" plants block the way)
+trait Tagged {
+    fn tags(&self) -> &[String];
+
+    fn has_tag(&self, tag: &str) -> bool {
+        self.tags().iter().any(|t| t == tag)
+    }
+}
+
+// Assets of the registered entities are always requested on registration, a
+// missing one is reported instead of crashing the game
+fn get_model(path: String, resources: &Resources) -> Option<Handle<Scene>> {
+    let model = resources.models.get(&path).cloned();
+    if model.is_none() {
+        error!("Model not loaded: {}", path);
     }
+    model
 }
 
+// Assets may be shared by several entity types (e.g. a glTF file holding many
+// models), so loading one twice is allowed
 fn load_model(
     path: String,
     resources: &mut Resources,
     asset_server: &Res<AssetServer>,
 ) {
     if resources.models.contains_key(&path) {
-        panic!("Model already loaded: {}", path);
+        return;
     }
     resources
         .models
         .insert(path.clone(), asset_server.load(path));
 }
 
+fn get_mesh(path: String, resources: &Resources) -> Option<Handle<Mesh>> {
+    let mesh = resources.meshes.get(&path).cloned();
+    if mesh.is_none() {
+        error!("Mesh not loaded: {}", path);
+    }
+    mesh
+}
+
+fn load_mesh(
+    path: String,
+    resources: &mut Resources,
+    asset_server: &Res<AssetServer>,
+) {
+    if resources.meshes.contains_key(&path) {
+        return;
+    }
+    resources
+        .meshes
+        .insert(path.clone(), asset_server.load(path));
+}
+
+fn get_material(
+    path: String,
+    resources: &Resources,
+) -> Option<Handle<StandardMaterial>> {
+    let material = resources.materials.get(&path).cloned();
+    if material.is_none() {
+        error!("Material not loaded: {}", path);
+    }
+    material
+}
+
+fn load_material(
+    path: String,
+    resources: &mut Resources,
+    asset_server: &Res<AssetServer>,
+) {
+    if resources.materials.contains_key(&path) {
+        return;
+    }
+    resources
+        .materials
+        .insert(path.clone(), asset_server.load(path));
+}
+
 fn load_animation(
     path: String,
     resources: &mut Resources,
     asset_server: &Res<AssetServer>,
 ) {
     if resources.animations.contains_key(&path) {
-        panic!("Animation already loaded: {}", path);
+        return;
     }
     resources
         .animations
         .insert(path.clone(), asset_server.load(path));
 }
 
-fn get_animation(path: String, resources: &Resources) -> Handle<AnimationClip> {
-    match resources.animations.get(&path) {
-        Some(animation) => animation.clone(),
-        None => panic!("Animation not loaded: {}", path),
+fn get_animation(
+    path: String,
+    resources: &Resources,
+) -> Option<Handle<AnimationClip>> {
+    let animation = resources.animations.get(&path).cloned();
+    if animation.is_none() {
+        error!("Animation not loaded: {}", path);
     }
+    animation
 }
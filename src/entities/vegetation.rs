@@ -3,83 +3,167 @@ use super::*;
 
 // Core ------------------------------------------------------------------------
 
-#[derive(Component)]
+#[derive(Component, Debug, PartialEq)]
 pub struct Entity {
     pub coordinates: IVec3,
     pub variant: Variant,
 }
 
-#[derive(Component, PartialEq)]
-pub enum Variant {
-    Bamboo,
-    Corn,
-    Grass,
-    Tree,
-}
+#[derive(
+    Component,
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    Hash,
+    PartialOrd,
+    Ord,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+#[serde(transparent)]
+pub struct Variant(pub Id);
 
 // Helpers ---------------------------------------------------------------------
 
-pub fn spawn(commands: &mut Commands, resources: &Resources, entity: Entity) {
-    commands.spawn((
-        SceneBundle {
-            scene: get_model(&entity.variant, resources),
-            transform: Transform {
-                translation: Vec3::new(
-                    entity.coordinates.x as f32,
-                    entity.coordinates.y as f32 - 0.5,
-                    entity.coordinates.z as f32,
-                ),
-                scale: Vec3::splat(0.5),
-                ..Default::default()
-            },
-            ..Default::default()
-        },
-        entity,
-    ));
+// Returns `None` (nothing is spawned) when the variant is not registered
+pub fn spawn(
+    commands: &mut Commands,
+    resources: &Resources,
+    entity: Entity,
+) -> Option<bevy::ecs::entity::Entity> {
+    let definition = get_definition(&entity.variant, resources)?;
+    let scene = core_get_model(definition.model.clone(), resources)?;
+
+    Some(
+        commands
+            .spawn((
+                SceneBundle {
+                    scene,
+                    transform: get_transform(&entity, definition),
+                    ..Default::default()
+                },
+                entity,
+            ))
+            .id(),
+    )
+}
+
+fn get_transform(entity: &Entity, definition: &Definition) -> Transform {
+    Transform {
+        translation: entity.coordinates.as_vec3()
+            + Vec3::Y * definition.y_offset,
+        scale: Vec3::splat(definition.scale),
+        ..Default::default()
+    }
+}
+
+// Applies the current definition of the plant to an already spawned one
+pub fn apply(
+    entity: &Entity,
+    scene: &mut Handle<Scene>,
+    transform: &mut Transform,
+    resources: &Resources,
+) {
+    let Some(definition) = get_definition(&entity.variant, resources) else {
+        return;
+    };
+    let Some(model) = core_get_model(definition.model.clone(), resources)
+    else {
+        return;
+    };
+
+    if *scene != model {
+        *scene = model;
+    }
+    *transform = get_transform(entity, definition);
+}
+
+// Definitions -----------------------------------------------------------------
+
+fn default_scale() -> f32 {
+    1.0
+}
+
+fn default_height() -> i32 {
+    1
 }
 
-// Variants & Animations -------------------------------------------------------
+#[derive(Clone, Debug, serde::Deserialize)]
+pub struct Definition {
+    pub name: Variant,
+    pub model: String,
+    #[serde(default = "default_scale")]
+    pub scale: f32,
+    // Vertical offset of the model from the center of its cell
+    #[serde(default)]
+    pub y_offset: f32,
+    // Cells covered by the model, from the one of the plant upwards
+    #[serde(default = "default_height")]
+    pub height: i32,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
 
-struct ModelData<'a> {
-    path: &'a str,
-    variant: Variant,
+impl Tagged for Definition {
+    fn tags(&self) -> &[String] {
+        &self.tags
+    }
 }
 
-const MODELS: [ModelData; 4] = [
-    ModelData {
-        path: "models/vegetation_bamboo.glb#Scene0",
-        variant: Variant::Bamboo,
-    },
-    ModelData {
-        path: "models/vegetation_corn.glb#Scene0",
-        variant: Variant::Corn,
-    },
-    ModelData {
-        path: "models/vegetation_grass.glb#Scene0",
-        variant: Variant::Grass,
-    },
-    ModelData {
-        path: "models/vegetation_tree.glb#Scene0",
-        variant: Variant::Tree,
-    },
-];
-
-pub fn get_model(variant: &Variant, resources: &Resources) -> Handle<Scene> {
-    match MODELS.iter().find(|model| model.variant == *variant) {
-        Some(model) => core_get_model(model.path.to_string(), resources),
-        None => panic!("Error while loading model"),
+pub fn register(
+    definition: Definition,
+    resources: &mut Resources,
+    asset_server: &Res<AssetServer>,
+) {
+    if get_definition(&definition.name, resources).is_some() {
+        warn!("Plant {} is defined twice, ignoring", definition.name.0);
+        return;
     }
+    load_model(definition.model.clone(), resources, asset_server);
+    resources.vegetation.push(definition);
 }
 
-pub fn load_assets(resources: &mut Resources, asset_server: &Res<AssetServer>) {
-    for model in MODELS.iter() {
-        load_model(model.path.to_string(), resources, asset_server);
+fn get_definition<'a>(
+    variant: &Variant,
+    resources: &'a Resources,
+) -> Option<&'a Definition> {
+    let definition = resources
+        .vegetation
+        .iter()
+        .find(|definition| definition.name == *variant);
+    if definition.is_none() && resources.is_ready() {
+        warn_unknown("plant", variant.0);
     }
+    definition
+}
+
+pub fn has_tag(variant: &Variant, tag: &str, resources: &Resources) -> bool {
+    get_definition(variant, resources)
+        .is_some_and(|definition| definition.has_tag(tag))
+}
+
+pub fn get_height(variant: &Variant, resources: &Resources) -> i32 {
+    get_definition(variant, resources).map_or(1, |definition| definition.height)
+}
+
+// Height of the tallest plant
+pub fn max_height(resources: &Resources) -> i32 {
+    resources
+        .vegetation
+        .iter()
+        .map(|definition| definition.height)
+        .max()
+        .unwrap_or(1)
 }
 
 // Exportable ------------------------------------------------------------------
 
 pub mod exportable {
+    pub use super::get_height;
+    pub use super::has_tag;
+    pub use super::max_height;
     pub use super::spawn;
     pub use super::Entity;
     pub use super::Variant;
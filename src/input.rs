@@ -1,73 +1,629 @@
-use bevy::prelude::*;
+use bevy::{
+    input::{
+        gamepad::{GamepadConnection, GamepadConnectionEvent},
+        mouse::MouseMotion,
+        InputSystem,
+    },
+    prelude::*,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+
+use crate::{
+    camera::{CameraRig, CameraView},
+    player::Player,
+    settings::Settings,
+    state::AppState,
+    world::{CutAway, RaycastHit, VoxelWorld},
+};
+
+// Maximum distance between the camera and a targeted block
+const RAYCAST_DISTANCE: f32 = 200.0;
+
+// Actions ---------------------------------------------------------------------
+
+// What the player wants to do, independently of the device used to do it
+#[derive(
+    Serialize,
+    Deserialize,
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    Hash,
+    PartialOrd,
+    Ord,
+)]
+pub enum Action {
+    MoveForward,
+    MoveBack,
+    StrafeLeft,
+    StrafeRight,
+    Jump,
+    Sprint,
+    Crouch,
+    Interact,
+    BreakBlock,
+    PlaceBlock,
+    PanUp,
+    PanDown,
+    PanLeft,
+    PanRight,
+    RotateLeft,
+    RotateRight,
+    ToggleFollow,
+    CycleView,
+    Pause,
+    ToggleDebug,
+}
+
+impl Action {
+    pub const ALL: [Action; 20] = [
+        Action::MoveForward,
+        Action::MoveBack,
+        Action::StrafeLeft,
+        Action::StrafeRight,
+        Action::Jump,
+        Action::Sprint,
+        Action::Crouch,
+        Action::Interact,
+        Action::BreakBlock,
+        Action::PlaceBlock,
+        Action::PanUp,
+        Action::PanDown,
+        Action::PanLeft,
+        Action::PanRight,
+        Action::RotateLeft,
+        Action::RotateRight,
+        Action::ToggleFollow,
+        Action::CycleView,
+        Action::Pause,
+        Action::ToggleDebug,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::MoveForward => "Move forward",
+            Action::MoveBack => "Move back",
+            Action::StrafeLeft => "Strafe left",
+            Action::StrafeRight => "Strafe right",
+            Action::Jump => "Jump",
+            Action::Sprint => "Sprint",
+            Action::Crouch => "Crouch",
+            Action::Interact => "Interact",
+            Action::BreakBlock => "Break block",
+            Action::PlaceBlock => "Place block",
+            Action::PanUp => "Pan up",
+            Action::PanDown => "Pan down",
+            Action::PanLeft => "Pan left",
+            Action::PanRight => "Pan right",
+            Action::RotateLeft => "Rotate left",
+            Action::RotateRight => "Rotate right",
+            Action::ToggleFollow => "Follow player",
+            Action::CycleView => "Change view",
+            Action::Pause => "Pause",
+            Action::ToggleDebug => "Toggle debug",
+        }
+    }
+
+    fn default_bindings(&self) -> Vec<Binding> {
+        use GamepadButtonType as Pad;
+
+        match self {
+            Action::MoveForward => {
+                vec![Binding::Key(KeyCode::KeyW), Binding::Gamepad(Pad::DPadUp)]
+            }
+            Action::MoveBack => vec![
+                Binding::Key(KeyCode::KeyS),
+                Binding::Gamepad(Pad::DPadDown),
+            ],
+            Action::StrafeLeft => vec![
+                Binding::Key(KeyCode::KeyA),
+                Binding::Gamepad(Pad::DPadLeft),
+            ],
+            Action::StrafeRight => vec![
+                Binding::Key(KeyCode::KeyD),
+                Binding::Gamepad(Pad::DPadRight),
+            ],
+            Action::Jump => {
+                vec![Binding::Key(KeyCode::Space), Binding::Gamepad(Pad::South)]
+            }
+            Action::Sprint => vec![
+                Binding::Key(KeyCode::ShiftLeft),
+                Binding::Gamepad(Pad::LeftThumb),
+            ],
+            Action::Crouch => vec![
+                Binding::Key(KeyCode::ControlLeft),
+                Binding::Gamepad(Pad::East),
+            ],
+            Action::Interact => {
+                vec![Binding::Key(KeyCode::KeyF), Binding::Gamepad(Pad::West)]
+            }
+            Action::BreakBlock => vec![
+                Binding::Mouse(MouseButton::Left),
+                Binding::Gamepad(Pad::RightTrigger2),
+            ],
+            Action::PlaceBlock => vec![
+                Binding::Mouse(MouseButton::Right),
+                Binding::Gamepad(Pad::LeftTrigger2),
+            ],
+            Action::PanUp => vec![Binding::Key(KeyCode::ArrowUp)],
+            Action::PanDown => vec![Binding::Key(KeyCode::ArrowDown)],
+            Action::PanLeft => vec![Binding::Key(KeyCode::ArrowLeft)],
+            Action::PanRight => vec![Binding::Key(KeyCode::ArrowRight)],
+            Action::RotateLeft => vec![
+                Binding::Key(KeyCode::KeyQ),
+                Binding::Gamepad(Pad::LeftTrigger),
+            ],
+            Action::RotateRight => vec![
+                Binding::Key(KeyCode::KeyE),
+                Binding::Gamepad(Pad::RightTrigger),
+            ],
+            Action::ToggleFollow => {
+                vec![Binding::Key(KeyCode::KeyC), Binding::Gamepad(Pad::North)]
+            }
+            Action::CycleView => vec![
+                Binding::Key(KeyCode::KeyV),
+                Binding::Gamepad(Pad::RightThumb),
+            ],
+            Action::Pause => vec![
+                Binding::Key(KeyCode::Escape),
+                Binding::Gamepad(Pad::Start),
+            ],
+            Action::ToggleDebug => {
+                vec![Binding::Key(KeyCode::F3), Binding::Gamepad(Pad::Select)]
+            }
+        }
+    }
+}
+
+// Physical input bound to an action
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Binding {
+    Key(KeyCode),
+    Mouse(MouseButton),
+    Gamepad(GamepadButtonType),
+}
+
+impl Binding {
+    pub fn name(&self) -> String {
+        match self {
+            Binding::Key(key) => format!("{:?}", key),
+            Binding::Mouse(button) => format!("Mouse {:?}", button),
+            Binding::Gamepad(button) => format!("Pad {:?}", button),
+        }
+    }
+
+    // Keyboard and mouse are used together, the gamepad on its own
+    pub fn is_gamepad(&self) -> bool {
+        matches!(self, Binding::Gamepad(_))
+    }
+}
+
+// Bindings of every action, stored in the settings
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(transparent)]
+pub struct Bindings(BTreeMap<Action, Vec<Binding>>);
+
+impl Default for Bindings {
+    fn default() -> Self {
+        Bindings(
+            Action::ALL
+                .iter()
+                .map(|action| (*action, action.default_bindings()))
+                .collect(),
+        )
+    }
+}
+
+impl Bindings {
+    pub fn get(&self, action: Action) -> &[Binding] {
+        self.0
+            .get(&action)
+            .map_or(&[], |bindings| bindings.as_slice())
+    }
+
+    // Binds the input to the action, replacing its previous binding on the
+    // same device. Returns the other actions also bound to that input.
+    pub fn bind(&mut self, action: Action, binding: Binding) -> Vec<Action> {
+        let bindings = self.0.entry(action).or_default();
+        bindings.retain(|other| other.is_gamepad() != binding.is_gamepad());
+        bindings.push(binding);
+
+        self.0
+            .iter()
+            .filter(|(other, bindings)| {
+                **other != action && bindings.contains(&binding)
+            })
+            .map(|(other, _)| *other)
+            .collect()
+    }
+
+    // Actions missing from an older config file get their default bindings,
+    // except the ones already used by another action (defaults can move
+    // between versions)
+    pub fn complete(&mut self) {
+        for action in Action::ALL {
+            if self.0.contains_key(&action) {
+                continue;
+            }
+            let used = self.0.values().flatten().collect::<HashSet<_>>();
+            let bindings = action
+                .default_bindings()
+                .into_iter()
+                .filter(|binding| !used.contains(binding))
+                .collect();
+            self.0.insert(action, bindings);
+        }
+    }
+}
+
+// State of every action on the current frame
+#[derive(Resource, Default, Debug)]
+pub struct Actions {
+    pressed: HashSet<Action>,
+    just_pressed: HashSet<Action>,
+}
+
+impl Actions {
+    pub fn pressed(&self, action: Action) -> bool {
+        self.pressed.contains(&action)
+    }
+
+    pub fn just_pressed(&self, action: Action) -> bool {
+        self.just_pressed.contains(&action)
+    }
+}
+
+// Resources -------------------------------------------------------------------
 
 #[derive(Resource, Default)]
 pub struct InputData {
-    pub pressed_keys: Vec<KeyCode>,
     pub mouse_position: MousePosition,
+    // Block under the mouse cursor, or in front of the player when playing
+    // with a gamepad
+    pub target: Option<RaycastHit>,
+    // Distance moved by the mouse on this frame, even when the cursor is
+    // locked
+    pub mouse_motion: Vec2,
+    pub gamepad: GamepadInput,
 }
 
 #[derive(Default)]
 pub struct MousePosition {
     // Mouse position on the screen
     pub source: Vec2,
-    // Mouse position in the world floor (Y = 1.0)
-    pub coordinates: Vec3,
+    // Ray from the camera through the mouse position
+    pub ray: Option<Ray3d>,
+}
+
+#[derive(Default)]
+pub struct GamepadInput {
+    // Gamepad being read, another one takes over when it is unplugged
+    pub active: Option<Gamepad>,
+    // Whether the gamepad was used more recently than the mouse
+    pub in_use: bool,
+    // Stick positions once the dead zone is removed
+    pub left_stick: Vec2,
+    pub right_stick: Vec2,
+    // Sticks turned into directions on the ground, pushing a stick up points
+    // away from the camera
+    pub movement: Vec3,
+    pub aim: Option<Vec3>,
+}
+
+impl MousePosition {
+    // Point under the mouse cursor on the horizontal plane at `height`
+    pub fn point_at_height(&self, height: f32) -> Option<Vec3> {
+        let ray = self.ray?;
+        let distance = ray.intersect_plane(
+            Vec3::new(0.0, height, 0.0),
+            Plane3d::new(Vec3::Y),
+        )?;
+        Some(ray.get_point(distance))
+    }
 }
 
 pub struct InputPlugin;
 
 impl Plugin for InputPlugin {
     fn build(&self, app: &mut App) {
+        // Actions are read in every state, so nothing is left pressed after
+        // releasing a key on a menu
         app.init_resource::<InputData>()
-            .add_systems(Update, update_state);
+            .init_resource::<Actions>()
+            .add_systems(
+                PreUpdate,
+                (update_gamepads, update_actions).chain().after(InputSystem),
+            )
+            .add_systems(
+                Update,
+                update_state.run_if(in_state(AppState::InGame)),
+            );
     }
 }
 
-fn update_state(
+// Helpers ---------------------------------------------------------------------
+
+// Stick position with a radial dead zone, rescaled so that it still goes
+// smoothly from 0.0 to 1.0 once out of it
+fn read_stick(
+    axes: &Axis<GamepadAxis>,
+    gamepad: Gamepad,
+    [x, y]: [GamepadAxisType; 2],
+    dead_zone: f32,
+) -> Vec2 {
+    let dead_zone = dead_zone.clamp(0.0, 0.9);
+    let stick = Vec2::new(
+        axes.get(GamepadAxis::new(gamepad, x)).unwrap_or(0.0),
+        axes.get(GamepadAxis::new(gamepad, y)).unwrap_or(0.0),
+    );
+
+    let length = stick.length();
+    if length <= dead_zone {
+        return Vec2::ZERO;
+    }
+    stick / length * ((length - dead_zone) / (1.0 - dead_zone)).min(1.0)
+}
+
+// Direction on the ground matching the stick direction on the screen
+pub fn ground_direction(
+    camera_transform: &GlobalTransform,
+    stick: Vec2,
+) -> Vec3 {
+    let forward = camera_transform.forward();
+    let right = camera_transform.right();
+    let forward = Vec3::new(forward.x, 0.0, forward.z).normalize_or_zero();
+    let right = Vec3::new(right.x, 0.0, right.z).normalize_or_zero();
+    right * stick.x + forward * stick.y
+}
+
+// Systems ---------------------------------------------------------------------
+
+fn update_gamepads(
     mut input_data: ResMut<InputData>,
-    mut cursor_moved_events: EventReader<CursorMoved>,
-    keyboard_input: Res<ButtonInput<KeyCode>>,
-    camera_query: Query<(&Camera, &GlobalTransform)>,
+    mut connection_events: EventReader<GamepadConnectionEvent>,
+    settings: Res<Settings>,
+    gamepads: Res<Gamepads>,
+    axes: Res<Axis<GamepadAxis>>,
+    gamepad_input: Res<ButtonInput<GamepadButton>>,
 ) {
-    // Push the keys that were just pressed
-    for key in keyboard_input.get_just_pressed() {
-        input_data.pressed_keys.push(*key);
+    for event in connection_events.read() {
+        match &event.connection {
+            GamepadConnection::Connected(info) => {
+                info!("Gamepad {} connected: {}", event.gamepad.id, info.name)
+            }
+            GamepadConnection::Disconnected => {
+                info!("Gamepad {} disconnected", event.gamepad.id)
+            }
+        }
+    }
+
+    let gamepad = &mut input_data.gamepad;
+    if !gamepad
+        .active
+        .is_some_and(|active| gamepads.contains(active))
+    {
+        gamepad.active = gamepads.iter().next();
     }
+    let Some(active) = gamepad.active else {
+        gamepad.in_use = false;
+        gamepad.left_stick = Vec2::ZERO;
+        gamepad.right_stick = Vec2::ZERO;
+        return;
+    };
+
+    use GamepadAxisType as Stick;
+    gamepad.left_stick = read_stick(
+        &axes,
+        active,
+        [Stick::LeftStickX, Stick::LeftStickY],
+        settings.dead_zone,
+    );
+    gamepad.right_stick = read_stick(
+        &axes,
+        active,
+        [Stick::RightStickX, Stick::RightStickY],
+        settings.dead_zone,
+    );
 
-    // Remove the keys that were just released
-    for key in keyboard_input.get_just_released() {
-        input_data.pressed_keys.retain(|&k| k != *key);
+    // Moving the mouse switches back to it, see `update_state`
+    if gamepad.left_stick != Vec2::ZERO
+        || gamepad.right_stick != Vec2::ZERO
+        || gamepad_input.get_just_pressed().next().is_some()
+    {
+        gamepad.in_use = true;
     }
+}
 
+fn update_actions(
+    mut actions: ResMut<Actions>,
+    settings: Res<Settings>,
+    input_data: Res<InputData>,
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mouse_input: Res<ButtonInput<MouseButton>>,
+    gamepad_input: Res<ButtonInput<GamepadButton>>,
+) {
+    let is_gamepad_pressed = |button_type, just: bool| {
+        input_data.gamepad.active.is_some_and(|gamepad| {
+            let button = GamepadButton::new(gamepad, button_type);
+            if just {
+                gamepad_input.just_pressed(button)
+            } else {
+                gamepad_input.pressed(button)
+            }
+        })
+    };
+    let is_pressed = |binding: &Binding, just: bool| match binding {
+        Binding::Key(key) if just => keyboard_input.just_pressed(*key),
+        Binding::Key(key) => keyboard_input.pressed(*key),
+        Binding::Mouse(button) if just => mouse_input.just_pressed(*button),
+        Binding::Mouse(button) => mouse_input.pressed(*button),
+        Binding::Gamepad(button) => is_gamepad_pressed(*button, just),
+    };
+
+    actions.pressed.clear();
+    actions.just_pressed.clear();
+    for action in Action::ALL {
+        let bindings = settings.bindings.get(action);
+        if bindings.iter().any(|binding| is_pressed(binding, false)) {
+            actions.pressed.insert(action);
+        }
+        if bindings.iter().any(|binding| is_pressed(binding, true)) {
+            actions.just_pressed.insert(action);
+        }
+    }
+}
+
+fn update_state(
+    mut input_data: ResMut<InputData>,
+    mut cursor_moved_events: EventReader<CursorMoved>,
+    mut mouse_motion_events: EventReader<MouseMotion>,
+    voxel_world: Res<VoxelWorld>,
+    cut_away: Res<CutAway>,
+    camera_query: Query<(&Camera, &GlobalTransform, Option<&CameraRig>)>,
+    player_query: Query<&Transform, With<Player>>,
+) {
     // Get global mouse position and store it
     for event in cursor_moved_events.read() {
         input_data.mouse_position.source = event.position;
+        input_data.gamepad.in_use = false;
     }
+    input_data.mouse_motion =
+        mouse_motion_events.read().map(|event| event.delta).sum();
 
-    for (camera, global_transform) in camera_query.iter() {
-        // Get the 3D ray from the camera to the mouse position
-        let ray = camera
-            .viewport_to_world(
-                global_transform,
-                input_data.mouse_position.source,
-            )
-            .expect(
-                "InputPlugin :: update_state :: Unable to get ray from camera 
-                to mouse position: For some reason the ray from the camera to 
-                the mouse position is not available.",
-            );
+    let mut is_first_person = false;
+    for (camera, global_transform, rig) in camera_query.iter() {
+        // The first person view aims with the center of the screen, where the
+        // locked cursor would be
+        is_first_person =
+            rig.is_some_and(|rig| rig.view == CameraView::FirstPerson);
+        let source = match camera.logical_viewport_size() {
+            Some(size) if is_first_person => size / 2.0,
+            _ => input_data.mouse_position.source,
+        };
 
-        // Get the distance to the plane (ground)
-        let distance = ray
-            .intersect_plane(
-                Vec3::new(0.0, 1.0, 0.0),
-                Plane3d::new(Vec3::new(0.0, 1.0, 0.0)),
+        // Get the 3D ray from the camera to the mouse position, it's not
+        // available while the cursor is outside of the viewport
+        let ray = camera.viewport_to_world(global_transform, source);
+
+        // The blocks cut away to show the player can't be targeted
+        input_data.mouse_position.ray = ray;
+        input_data.target = ray.and_then(|ray| {
+            voxel_world.raycast(
+                ray.origin,
+                *ray.direction,
+                RAYCAST_DISTANCE,
+                &cut_away.blocks,
             )
-            .expect(
-                "InputPlugin :: update_state :: Unable to get distance between 
-                the camera and the ground: The ray does not intersect the ground.",
+        });
+
+        let gamepad = &mut input_data.gamepad;
+        gamepad.movement =
+            ground_direction(global_transform, gamepad.left_stick);
+        gamepad.aim = (gamepad.right_stick != Vec2::ZERO)
+            .then(|| ground_direction(global_transform, gamepad.right_stick));
+    }
+
+    // There is no cursor with a gamepad, the block in front of the player (and
+    // slightly below) is targeted instead
+    if input_data.gamepad.in_use && !is_first_person {
+        if let Ok(transform) = player_query.get_single() {
+            let direction = transform.rotation.mul_vec3(Vec3::Z) - Vec3::Y;
+            input_data.target = voxel_world.raycast(
+                transform.translation + Vec3::Y,
+                direction,
+                RAYCAST_DISTANCE,
+                &cut_away.blocks,
             );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn binding_replaces_the_one_on_the_same_device() {
+        let mut bindings = Bindings::default();
+        let others = bindings.bind(Action::Jump, Binding::Key(KeyCode::KeyJ));
+
+        assert!(others.is_empty());
+        assert_eq!(
+            bindings.get(Action::Jump),
+            &[
+                Binding::Gamepad(GamepadButtonType::South),
+                Binding::Key(KeyCode::KeyJ),
+            ]
+        );
+    }
+
+    #[test]
+    fn binding_reports_the_actions_sharing_the_input() {
+        let mut bindings = Bindings::default();
+        let others = bindings.bind(Action::Jump, Binding::Key(KeyCode::KeyW));
+
+        assert_eq!(others, vec![Action::MoveForward]);
+        // Both actions keep the input, the player decides which to change
+        assert!(bindings
+            .get(Action::MoveForward)
+            .contains(&Binding::Key(KeyCode::KeyW)));
+    }
+
+    #[test]
+    fn missing_actions_get_their_default_bindings() {
+        let mut bindings = Bindings::default();
+        bindings.0.remove(&Action::Jump);
+        bindings.complete();
+
+        assert_eq!(bindings, Bindings::default());
+    }
+
+    #[test]
+    fn defaults_already_used_are_skipped() {
+        let mut bindings = Bindings::default();
+        bindings.0.remove(&Action::Jump);
+        bindings.bind(Action::Interact, Binding::Key(KeyCode::Space));
+        bindings.complete();
+
+        assert_eq!(
+            bindings.get(Action::Jump),
+            &[Binding::Gamepad(GamepadButtonType::South)]
+        );
+    }
+
+    fn stick(x: f32, y: f32, dead_zone: f32) -> Vec2 {
+        let gamepad = Gamepad::new(0);
+        let mut axes = Axis::<GamepadAxis>::default();
+        axes.set(GamepadAxis::new(gamepad, GamepadAxisType::LeftStickX), x);
+        axes.set(GamepadAxis::new(gamepad, GamepadAxisType::LeftStickY), y);
+        read_stick(
+            &axes,
+            gamepad,
+            [GamepadAxisType::LeftStickX, GamepadAxisType::LeftStickY],
+            dead_zone,
+        )
+    }
+
+    #[test]
+    fn sticks_inside_the_dead_zone_are_ignored() {
+        assert_eq!(stick(0.1, 0.1, 0.2), Vec2::ZERO);
+        assert_eq!(stick(0.0, -0.2, 0.2), Vec2::ZERO);
+    }
+
+    #[test]
+    fn sticks_are_rescaled_out_of_the_dead_zone() {
+        // Halfway between the dead zone and the edge
+        let half = stick(0.6, 0.0, 0.2);
+        assert!((half - Vec2::new(0.5, 0.0)).length() < 1e-5);
 
-        input_data.mouse_position.coordinates = ray.get_point(distance);
+        // The direction is kept and the length never goes above 1.0
+        let full = stick(0.0, -1.0, 0.2);
+        assert!((full - Vec2::NEG_Y).length() < 1e-5);
+        let diagonal = stick(1.0, 1.0, 0.2);
+        assert!((diagonal.length() - 1.0).abs() < 1e-5);
+        assert!((diagonal.x - diagonal.y).abs() < 1e-5);
     }
 }
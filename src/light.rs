@@ -1,14 +1,27 @@
 use bevy::prelude::*;
 
+use crate::state::AppState;
+
+// Speed of the sun (and moon) around the world
+const DEGREES_PER_SECOND: f32 = 60.0;
+
 #[derive(Component, Debug)]
 pub struct SunMoonLight;
 
+// Rotation of the sun around the vertical axis, in degrees (0.0 to 360.0)
+#[derive(Resource, Default, Debug)]
+pub struct TimeOfDay(pub f32);
+
 pub struct LightPlugin;
 
 impl Plugin for LightPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, spawn_light)
-            .add_systems(Update, rotate_light);
+        app.init_resource::<TimeOfDay>()
+            .add_systems(Startup, spawn_light)
+            .add_systems(
+                Update,
+                rotate_light.run_if(in_state(AppState::InGame)),
+            );
     }
 }
 
@@ -35,8 +48,16 @@ fn spawn_light(mut commands: Commands) {
     ));
 }
 
-fn rotate_light(mut query: Query<(&SunMoonLight, &mut Transform)>) {
+fn rotate_light(
+    time: Res<Time>,
+    mut time_of_day: ResMut<TimeOfDay>,
+    mut query: Query<(&SunMoonLight, &mut Transform)>,
+) {
+    time_of_day.0 =
+        (time_of_day.0 + time.delta_seconds() * DEGREES_PER_SECOND) % 360.0;
+
     for (_, mut transform) in query.iter_mut() {
-        transform.rotate(Quat::from_rotation_y(-1.0_f32.to_radians()));
+        transform.rotation = Quat::from_rotation_y(-time_of_day.0.to_radians())
+            * Quat::from_rotation_x(310.0_f32.to_radians());
     }
 }
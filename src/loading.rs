@@ -0,0 +1,142 @@
+use bevy::{
+    asset::{RecursiveDependencyLoadState, UntypedAssetLoadFailedEvent},
+    prelude::*,
+};
+
+use crate::{
+    cli::Cli, entities::entity::Resources as EntityResources, state::AppState,
+};
+
+// Components ------------------------------------------------------------------
+
+#[derive(Component)]
+struct LoadingScreen;
+
+#[derive(Component)]
+struct LoadingText;
+
+// Resources -------------------------------------------------------------------
+
+// Assets that failed to load, with the reason given by the asset server
+#[derive(Resource, Default, Debug)]
+pub struct LoadingFailures(pub Vec<String>);
+
+// Plugin ----------------------------------------------------------------------
+
+pub struct LoadingPlugin;
+
+impl Plugin for LoadingPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<LoadingFailures>()
+            .add_systems(OnEnter(AppState::Loading), setup_screen)
+            .add_systems(
+                Update,
+                (collect_failures, update_progress)
+                    .chain()
+                    .run_if(in_state(AppState::Loading)),
+            )
+            .add_systems(OnExit(AppState::Loading), despawn_screen);
+    }
+}
+
+// Systems ---------------------------------------------------------------------
+
+fn setup_screen(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let style = TextStyle {
+        font: asset_server.load("fonts/FiraCode-Retina.ttf"),
+        font_size: 24.0,
+        color: Color::WHITE,
+    };
+
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                background_color: Color::BLACK.into(),
+                ..default()
+            },
+            LoadingScreen,
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                TextBundle::from_section("Loading...", style),
+                LoadingText,
+            ));
+        });
+}
+
+fn collect_failures(
+    mut failures: ResMut<LoadingFailures>,
+    mut events: EventReader<UntypedAssetLoadFailedEvent>,
+) {
+    for event in events.read() {
+        failures.0.push(format!("{}: {}", event.path, event.error));
+    }
+}
+
+// Waits for every asset of the registered entities to be loaded (or to fail)
+// before starting the game. When something failed the player is told what and
+// why, and may still continue.
+fn update_progress(
+    resources: Res<EntityResources>,
+    asset_server: Res<AssetServer>,
+    failures: Res<LoadingFailures>,
+    cli: Res<Cli>,
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mut next_state: ResMut<NextState<AppState>>,
+    mut text_query: Query<&mut Text, With<LoadingText>>,
+) {
+    let assets = resources.assets();
+    let settled = assets
+        .iter()
+        .filter(|id| {
+            matches!(
+                asset_server.get_recursive_dependency_load_state(**id),
+                Some(RecursiveDependencyLoadState::Loaded)
+                    | Some(RecursiveDependencyLoadState::Failed)
+            )
+        })
+        .count();
+    let is_done = resources.is_ready() && settled == assets.len();
+
+    if is_done && failures.0.is_empty() {
+        next_state.set(AppState::InGame);
+        return;
+    }
+
+    let mut message =
+        format!("Loading... {}%", settled * 100 / assets.len().max(1));
+    if !failures.0.is_empty() {
+        message.push_str("\n\nSome assets failed to load:\n");
+        for failure in failures.0.iter() {
+            message.push_str(&format!("- {}\n", failure));
+        }
+    }
+    if is_done {
+        // Nobody can press Enter on unattended runs
+        let is_unattended = cli.headless || cli.benchmark.is_some();
+        message.push_str("\nPress Enter to continue anyway");
+        if is_unattended || keyboard_input.just_pressed(KeyCode::Enter) {
+            next_state.set(AppState::InGame);
+        }
+    }
+
+    for mut text in text_query.iter_mut() {
+        text.sections[0].value = message.clone();
+    }
+}
+
+fn despawn_screen(
+    mut commands: Commands,
+    screen_query: Query<Entity, With<LoadingScreen>>,
+) {
+    for entity in screen_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
@@ -1,29 +1,51 @@
 mod animation;
+mod benchmark;
+mod building;
 mod camera;
+mod cli;
 mod debug;
-mod despawn;
 mod entities;
 mod input;
 mod light;
+mod loading;
+mod menu;
+mod occlusion;
+mod physics;
 mod player;
+mod save;
+mod settings;
+mod state;
 mod system_info;
+mod world;
 
 use bevy::{
+    app::ScheduleRunnerPlugin,
     prelude::*,
-    window::{
-        PresentMode, WindowLevel, WindowMode, WindowPosition, WindowResolution,
-    },
+    render::{settings::WgpuSettings, RenderPlugin},
+    window::ExitCondition,
+    winit::WinitPlugin,
 };
+use std::time::Duration;
 
 use animation::AnimationPlugin;
+use benchmark::BenchmarkPlugin;
+use building::BuildingPlugin;
 // use bevy_inspector_egui::quick::WorldInspectorPlugin;
 use camera::CameraPlugin;
+use cli::{Cli, CliPlugin};
 use debug::DebugPlugin;
-use despawn::DespawnPlugin;
 use entities::EntityPlugin;
 use input::InputPlugin;
 use light::LightPlugin;
+use loading::LoadingPlugin;
+use menu::MenuPlugin;
+use occlusion::OcclusionPlugin;
+use physics::PhysicsPlugin;
 use player::PlayerPlugin;
+use save::SavePlugin;
+use settings::{Settings, SettingsPlugin};
+use state::StatePlugin;
+use world::{WorldPlugin, WorldSeed};
 
 // ToDo (sorted by priority):
 // -> Improve animation logic
@@ -31,30 +53,68 @@ use player::PlayerPlugin;
 // -> Fix issues on player rotation (it needs to always looks at the mouse)
 // -> Improve debug mode code (enable/disable)
 // -> Fix system info module (CPU usage is not accurate, CPU temp is missing, AMD gpus are not supported)
-// -> Improve camera (add zoom and movement)
 
 fn main() {
-    App::new()
-        .add_plugins((DefaultPlugins.set(WindowPlugin {
-            primary_window: Some(Window {
-                mode: WindowMode::Windowed,
-                title: "Genesys Ultimate".into(),
-                present_mode: PresentMode::AutoVsync,
-                window_level: WindowLevel::AlwaysOnTop,
-                position: WindowPosition::At(IVec2::new(-1085, -160)),
-                resolution: WindowResolution::new(1075., 610.),
-                ..default()
-            }),
+    let cli = Cli::parse();
+
+    // The window is created from the settings, they have to be read first
+    let mut settings = Settings::load();
+    settings.mode_override = cli.window_mode;
+
+    let mut app = App::new();
+    if cli.headless {
+        // No window and no GPU, the game loop is driven by a timer instead
+        app.add_plugins((
+            DefaultPlugins
+                .set(WindowPlugin {
+                    primary_window: None,
+                    exit_condition: ExitCondition::DontExit,
+                    close_when_requested: false,
+                })
+                .set(RenderPlugin {
+                    render_creation: WgpuSettings {
+                        backends: None,
+                        ..default()
+                    }
+                    .into(),
+                    ..default()
+                })
+                .disable::<WinitPlugin>(),
+            ScheduleRunnerPlugin::run_loop(Duration::from_secs_f64(1.0 / 60.0)),
+        ));
+    } else {
+        app.add_plugins((DefaultPlugins.set(WindowPlugin {
+            primary_window: Some(settings.window()),
             ..default()
-        }),))
-        // .add_plugins(WorldInspectorPlugin::new())
+        }),));
+    }
+
+    // app.add_plugins(WorldInspectorPlugin::new());
+    app.insert_resource(cli.seed.unwrap_or_else(WorldSeed::from_env))
+        .insert_resource(cli.save_config())
+        .insert_resource(settings)
+        .add_plugins(SettingsPlugin)
+        .add_plugins(StatePlugin)
+        .add_plugins(LoadingPlugin)
+        .add_plugins(MenuPlugin)
         .add_plugins(CameraPlugin)
+        .add_plugins(OcclusionPlugin)
         .add_plugins(LightPlugin)
         .add_plugins(PlayerPlugin)
+        .add_plugins(PhysicsPlugin)
         .add_plugins(EntityPlugin)
+        .add_plugins(WorldPlugin)
         .add_plugins(AnimationPlugin)
-        .add_plugins(DebugPlugin)
         .add_plugins(InputPlugin)
-        .add_plugins(DespawnPlugin)
-        .run();
+        .add_plugins(BuildingPlugin)
+        .add_plugins(SavePlugin);
+
+    if !cli.no_debug && !cli.headless {
+        app.add_plugins(DebugPlugin);
+    }
+    if let Some(duration) = cli.benchmark {
+        app.add_plugins(BenchmarkPlugin { duration });
+    }
+
+    app.insert_resource(cli).add_plugins(CliPlugin).run();
 }
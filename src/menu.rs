@@ -0,0 +1,740 @@
+use bevy::{
+    app::AppExit, ecs::system::SystemParam, prelude::*,
+    window::ReceivedCharacter,
+};
+
+use crate::{
+    input::{Action, Binding, Bindings},
+    player::MovementMode,
+    save::{LoadFailed, StartWorld},
+    settings::{DisplayMode, Settings},
+    state::AppState,
+    world::WorldSeed,
+};
+
+const FONT: &str = "fonts/FiraCode-Retina.ttf";
+
+const BUTTON_COLOR: Color = Color::rgba(0.1, 0.1, 0.1, 0.8);
+const FOCUSED_COLOR: Color = Color::rgba(0.3, 0.3, 0.3, 0.9);
+
+// Longest seed that can be typed
+const MAX_SEED_LENGTH: usize = 32;
+
+// Window sizes offered by the settings screen
+const RESOLUTIONS: [[f32; 2]; 5] = [
+    [1075.0, 610.0],
+    [1280.0, 720.0],
+    [1600.0, 900.0],
+    [1920.0, 1080.0],
+    [2560.0, 1440.0],
+];
+
+const MAX_VIEW_DISTANCE: i32 = 8;
+
+// Buttons of the controls screen, one per action
+const CONTROLS: [MenuAction; 24] = [
+    MenuAction::Rebind(Action::MoveForward),
+    MenuAction::Rebind(Action::MoveBack),
+    MenuAction::Rebind(Action::StrafeLeft),
+    MenuAction::Rebind(Action::StrafeRight),
+    MenuAction::Rebind(Action::Jump),
+    MenuAction::Rebind(Action::Sprint),
+    MenuAction::Rebind(Action::Crouch),
+    MenuAction::Rebind(Action::Interact),
+    MenuAction::Rebind(Action::BreakBlock),
+    MenuAction::Rebind(Action::PlaceBlock),
+    MenuAction::Rebind(Action::PanUp),
+    MenuAction::Rebind(Action::PanDown),
+    MenuAction::Rebind(Action::PanLeft),
+    MenuAction::Rebind(Action::PanRight),
+    MenuAction::Rebind(Action::RotateLeft),
+    MenuAction::Rebind(Action::RotateRight),
+    MenuAction::Rebind(Action::ToggleFollow),
+    MenuAction::Rebind(Action::CycleView),
+    MenuAction::Rebind(Action::Pause),
+    MenuAction::Rebind(Action::ToggleDebug),
+    MenuAction::MovementMode,
+    MenuAction::DeadZone,
+    MenuAction::ResetControls,
+    MenuAction::Back,
+];
+
+// Core ------------------------------------------------------------------------
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Screen {
+    Main,
+    Pause,
+    Settings,
+    Controls,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum MenuAction {
+    NewWorld,
+    LoadWorld,
+    Settings,
+    Resume,
+    // Leaves the world for the main menu, the game keeps running
+    QuitToMenu,
+    Quit,
+    Back,
+    // Settings, activating them cycles through their values
+    DisplayMode,
+    Resolution,
+    VSync,
+    Shadows,
+    ViewDistance,
+    Volume,
+    MovementMode,
+    DeadZone,
+    Controls,
+    // Waits for the next input and binds it to the action
+    Rebind(Action),
+    ResetControls,
+}
+
+impl Screen {
+    fn title(&self) -> &'static str {
+        match self {
+            Screen::Main => "Genesys Ultimate",
+            Screen::Pause => "Paused",
+            Screen::Settings => "Settings",
+            Screen::Controls => "Controls",
+        }
+    }
+
+    fn actions(&self) -> &'static [MenuAction] {
+        match self {
+            Screen::Main => &[
+                MenuAction::NewWorld,
+                MenuAction::LoadWorld,
+                MenuAction::Settings,
+                MenuAction::Quit,
+            ],
+            Screen::Pause => &[
+                MenuAction::Resume,
+                MenuAction::Settings,
+                MenuAction::QuitToMenu,
+                MenuAction::Quit,
+            ],
+            Screen::Settings => &[
+                MenuAction::DisplayMode,
+                MenuAction::Resolution,
+                MenuAction::VSync,
+                MenuAction::Shadows,
+                MenuAction::ViewDistance,
+                MenuAction::Volume,
+                MenuAction::Controls,
+                MenuAction::Back,
+            ],
+            Screen::Controls => &CONTROLS,
+        }
+    }
+}
+
+impl MenuAction {
+    fn label(&self, settings: &Settings) -> String {
+        let on_off = |value: bool| if value { "On" } else { "Off" };
+        match self {
+            MenuAction::NewWorld => "New World".to_string(),
+            MenuAction::LoadWorld => "Load World".to_string(),
+            MenuAction::Settings => "Settings".to_string(),
+            MenuAction::Resume => "Resume".to_string(),
+            MenuAction::QuitToMenu => "Quit to Menu".to_string(),
+            MenuAction::Quit => "Quit".to_string(),
+            MenuAction::Back => "Back".to_string(),
+            MenuAction::DisplayMode => {
+                format!("Display: {:?}", settings.display_mode())
+            }
+            MenuAction::Resolution => {
+                let [width, height] = settings.window.resolution;
+                format!("Resolution: {}x{}", width, height)
+            }
+            MenuAction::VSync => {
+                format!("VSync: {}", on_off(settings.window.vsync))
+            }
+            MenuAction::Shadows => {
+                format!("Shadows: {}", on_off(settings.shadows))
+            }
+            MenuAction::ViewDistance => {
+                format!("View distance: {}", settings.view_distance)
+            }
+            MenuAction::Volume => {
+                format!("Volume: {}%", (settings.volume * 100.0).round())
+            }
+            MenuAction::MovementMode => {
+                format!("Movement: {}", settings.movement_mode.name())
+            }
+            MenuAction::DeadZone => format!(
+                "Stick dead zone: {}%",
+                (settings.dead_zone * 100.0).round()
+            ),
+            MenuAction::Controls => "Controls".to_string(),
+            MenuAction::Rebind(action) => {
+                let bindings = settings
+                    .bindings
+                    .get(*action)
+                    .iter()
+                    .map(|binding| binding.name())
+                    .collect::<Vec<_>>();
+                format!("{}: {}", action.name(), bindings.join(", "))
+            }
+            MenuAction::ResetControls => "Reset controls".to_string(),
+        }
+    }
+
+    // Moves the setting to its next (or previous) value, returns false when
+    // the action is not a setting
+    fn adjust(&self, settings: &mut Settings, step: i32) -> bool {
+        match self {
+            MenuAction::DisplayMode => {
+                let modes = [
+                    DisplayMode::Windowed,
+                    DisplayMode::Borderless,
+                    DisplayMode::Fullscreen,
+                ];
+                let current = modes
+                    .iter()
+                    .position(|mode| *mode == settings.display_mode())
+                    .unwrap_or(0);
+                // Picking a mode in the menu replaces the command line one
+                settings.window.mode = modes[cycle(current, step, modes.len())];
+                settings.mode_override = None;
+            }
+            MenuAction::Resolution => {
+                let current = RESOLUTIONS
+                    .iter()
+                    .position(|size| *size == settings.window.resolution)
+                    .unwrap_or(0);
+                settings.window.resolution =
+                    RESOLUTIONS[cycle(current, step, RESOLUTIONS.len())];
+            }
+            MenuAction::VSync => {
+                settings.window.vsync = !settings.window.vsync;
+            }
+            MenuAction::Shadows => settings.shadows = !settings.shadows,
+            MenuAction::ViewDistance => {
+                settings.view_distance =
+                    (settings.view_distance + step).clamp(1, MAX_VIEW_DISTANCE);
+            }
+            MenuAction::Volume => {
+                let volume = (settings.volume * 10.0).round() as i32 + step;
+                settings.volume = volume.clamp(0, 10) as f32 / 10.0;
+            }
+            MenuAction::MovementMode => {
+                settings.movement_mode = match settings.movement_mode {
+                    MovementMode::Facing => MovementMode::Camera,
+                    MovementMode::Camera => MovementMode::Facing,
+                };
+            }
+            MenuAction::DeadZone => {
+                let dead_zone =
+                    (settings.dead_zone * 20.0).round() as i32 + step;
+                settings.dead_zone = dead_zone.clamp(0, 10) as f32 / 20.0;
+            }
+            _ => return false,
+        }
+        true
+    }
+}
+
+fn cycle(index: usize, step: i32, length: usize) -> usize {
+    (index as i32 + step).rem_euclid(length as i32) as usize
+}
+
+// Components ------------------------------------------------------------------
+
+#[derive(Component)]
+struct Menu;
+
+#[derive(Component)]
+struct MenuButton {
+    action: MenuAction,
+    // Position of the button in the menu, used by the keyboard navigation
+    index: usize,
+}
+
+#[derive(Component)]
+struct ButtonLabel;
+
+// Texts of the screen filled from the menu state
+#[derive(Component, Clone, Copy, PartialEq, Eq)]
+enum MenuText {
+    Seed,
+    Message,
+}
+
+// Resources -------------------------------------------------------------------
+
+#[derive(Resource, Default, Debug)]
+pub struct MenuState {
+    // Screen being shown, `None` while playing
+    screen: Option<Screen>,
+    // Screen to go back to from the settings
+    previous: Option<Screen>,
+    focus: usize,
+    // Action waiting for its new binding
+    rebinding: Option<Action>,
+    seed: String,
+    message: String,
+}
+
+impl MenuState {
+    fn open(&mut self, screen: Screen) {
+        if matches!(self.screen, Some(Screen::Main | Screen::Pause)) {
+            self.previous = self.screen;
+        }
+        self.screen = Some(screen);
+        self.focus = 0;
+        self.message.clear();
+    }
+
+    fn actions(&self) -> &'static [MenuAction] {
+        self.screen.map_or(&[], |screen| screen.actions())
+    }
+}
+
+// Keyboard or gamepad buttons used to move through the menus
+#[derive(SystemParam)]
+struct MenuInput<'w> {
+    keyboard_input: Res<'w, ButtonInput<KeyCode>>,
+    gamepad_input: Res<'w, ButtonInput<GamepadButton>>,
+}
+
+impl MenuInput<'_> {
+    fn just_pressed(
+        &self,
+        key: KeyCode,
+        button_type: GamepadButtonType,
+    ) -> bool {
+        self.keyboard_input.just_pressed(key)
+            || self
+                .gamepad_input
+                .get_just_pressed()
+                .any(|button| button.button_type == button_type)
+    }
+}
+
+// Plugin ----------------------------------------------------------------------
+
+// Systems reading the input on the menus
+#[derive(SystemSet, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct MenuSet;
+
+pub struct MenuPlugin;
+
+impl Plugin for MenuPlugin {
+    fn build(&self, app: &mut App) {
+        let in_menu =
+            in_state(AppState::MainMenu).or_else(in_state(AppState::Paused));
+
+        app.init_resource::<MenuState>()
+            .add_systems(OnEnter(AppState::MainMenu), open_main_menu)
+            .add_systems(OnEnter(AppState::Paused), open_pause_menu)
+            .add_systems(OnExit(AppState::MainMenu), close_menu)
+            .add_systems(OnExit(AppState::Paused), close_menu)
+            .add_systems(
+                Update,
+                (
+                    rebuild_menu,
+                    // The capture runs after the navigation, which ignores
+                    // the input being captured
+                    (type_seed, show_failures, navigate, capture_binding)
+                        .chain()
+                        .in_set(MenuSet)
+                        .run_if(in_menu),
+                    update_menu,
+                )
+                    .chain(),
+            );
+    }
+}
+
+// Helpers ---------------------------------------------------------------------
+
+fn text_style(asset_server: &AssetServer, font_size: f32) -> TextStyle {
+    TextStyle {
+        font: asset_server.load(FONT),
+        font_size,
+        color: Color::WHITE,
+    }
+}
+
+// Full screen column holding the title and the buttons of a screen
+fn spawn_screen(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    screen: Screen,
+) {
+    // The game stays visible behind the pause menu
+    let background = match screen {
+        Screen::Main => Color::BLACK,
+        _ => Color::rgba(0.0, 0.0, 0.0, 0.6),
+    };
+
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    row_gap: Val::Px(12.0),
+                    ..default()
+                },
+                background_color: background.into(),
+                ..default()
+            },
+            Menu,
+        ))
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                screen.title(),
+                text_style(asset_server, 48.0),
+            ));
+            if screen == Screen::Main {
+                parent.spawn((
+                    TextBundle::from_section(
+                        "",
+                        text_style(asset_server, 18.0),
+                    ),
+                    MenuText::Seed,
+                ));
+            }
+            parent
+                .spawn(NodeBundle {
+                    style: buttons_style(screen),
+                    ..default()
+                })
+                .with_children(|parent| {
+                    for (index, action) in screen.actions().iter().enumerate() {
+                        spawn_button(
+                            parent,
+                            asset_server,
+                            *action,
+                            index,
+                            screen == Screen::Controls,
+                        );
+                    }
+                });
+            parent.spawn((
+                TextBundle::from_section("", text_style(asset_server, 18.0)),
+                MenuText::Message,
+            ));
+        });
+}
+
+fn buttons_style(screen: Screen) -> Style {
+    match screen {
+        // The controls don't fit in a single column
+        Screen::Controls => Style {
+            width: Val::Px(980.0),
+            flex_wrap: FlexWrap::Wrap,
+            justify_content: JustifyContent::Center,
+            row_gap: Val::Px(8.0),
+            column_gap: Val::Px(10.0),
+            ..default()
+        },
+        _ => Style {
+            flex_direction: FlexDirection::Column,
+            row_gap: Val::Px(12.0),
+            ..default()
+        },
+    }
+}
+
+fn spawn_button(
+    parent: &mut ChildBuilder,
+    asset_server: &AssetServer,
+    action: MenuAction,
+    index: usize,
+    compact: bool,
+) {
+    let (font_size, padding) = if compact { (18.0, 6.0) } else { (24.0, 10.0) };
+    parent
+        .spawn((
+            ButtonBundle {
+                style: Style {
+                    width: Val::Px(320.0),
+                    padding: UiRect::all(Val::Px(padding)),
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                background_color: BUTTON_COLOR.into(),
+                ..default()
+            },
+            MenuButton { action, index },
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                TextBundle::from_section(
+                    "",
+                    text_style(asset_server, font_size),
+                ),
+                ButtonLabel,
+            ));
+        });
+}
+
+// Systems ---------------------------------------------------------------------
+
+fn open_main_menu(mut menu: ResMut<MenuState>, seed: Res<WorldSeed>) {
+    menu.open(Screen::Main);
+    // The seed given on the command line (or a random one) is suggested
+    menu.seed = seed.0.to_string();
+}
+
+fn open_pause_menu(mut menu: ResMut<MenuState>) {
+    menu.open(Screen::Pause);
+}
+
+fn close_menu(mut menu: ResMut<MenuState>) {
+    menu.screen = None;
+    menu.rebinding = None;
+}
+
+// Spawns the UI of the current screen whenever it changes
+fn rebuild_menu(
+    mut commands: Commands,
+    mut menu: ResMut<MenuState>,
+    mut shown: Local<Option<Screen>>,
+    asset_server: Res<AssetServer>,
+    menu_query: Query<Entity, With<Menu>>,
+) {
+    if *shown == menu.screen {
+        return;
+    }
+    *shown = menu.screen;
+
+    for entity in menu_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    if let Some(screen) = menu.screen {
+        spawn_screen(&mut commands, &asset_server, screen);
+    }
+    // Labels of the new buttons are filled by `update_menu`
+    menu.set_changed();
+}
+
+// Any key typed on the main menu goes to the seed field
+fn type_seed(
+    mut menu: ResMut<MenuState>,
+    mut characters: EventReader<ReceivedCharacter>,
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+) {
+    if menu.screen != Some(Screen::Main) {
+        characters.clear();
+        return;
+    }
+
+    for event in characters.read() {
+        for character in event.char.chars() {
+            let is_valid = character.is_alphanumeric()
+                || character == ' '
+                || character == '-'
+                || character == '_';
+            if is_valid && menu.seed.chars().count() < MAX_SEED_LENGTH {
+                menu.seed.push(character);
+            }
+        }
+    }
+    if keyboard_input.just_pressed(KeyCode::Backspace) {
+        menu.seed.pop();
+    }
+}
+
+fn show_failures(
+    mut menu: ResMut<MenuState>,
+    mut failures: EventReader<LoadFailed>,
+) {
+    for failure in failures.read() {
+        menu.message = format!("Unable to load the world: {}", failure.0);
+    }
+}
+
+// Arrow keys (the D-pad, or the mouse) move the focus between the buttons,
+// Enter (the south button, or a click) activates the focused one. Left and
+// right change the focused setting.
+fn navigate(
+    mut menu: ResMut<MenuState>,
+    mut settings: ResMut<Settings>,
+    mut start_events: EventWriter<StartWorld>,
+    mut exit_events: EventWriter<AppExit>,
+    mut next_state: ResMut<NextState<AppState>>,
+    input: MenuInput,
+    button_query: Query<(&MenuButton, &Interaction), Changed<Interaction>>,
+) {
+    use GamepadButtonType as Pad;
+
+    let actions = menu.actions();
+    if actions.is_empty() || menu.rebinding.is_some() {
+        return;
+    }
+    if input.just_pressed(KeyCode::ArrowDown, Pad::DPadDown) {
+        menu.focus = cycle(menu.focus, 1, actions.len());
+    }
+    if input.just_pressed(KeyCode::ArrowUp, Pad::DPadUp) {
+        menu.focus = cycle(menu.focus, -1, actions.len());
+    }
+
+    let mut activated = None;
+    for (button, interaction) in button_query.iter() {
+        match interaction {
+            Interaction::Hovered => menu.focus = button.index,
+            Interaction::Pressed => activated = Some(button.action),
+            Interaction::None => {}
+        }
+    }
+
+    let focused = actions[menu.focus.min(actions.len() - 1)];
+    if input.just_pressed(KeyCode::Enter, Pad::South) {
+        activated = Some(focused);
+    }
+    for (key, button, step) in [
+        (KeyCode::ArrowLeft, Pad::DPadLeft, -1),
+        (KeyCode::ArrowRight, Pad::DPadRight, 1),
+    ] {
+        if input.just_pressed(key, button) {
+            focused.adjust(&mut settings, step);
+        }
+    }
+
+    let Some(action) = activated else {
+        return;
+    };
+    if action.adjust(&mut settings, 1) {
+        return;
+    }
+    match action {
+        MenuAction::NewWorld => {
+            let seed = match menu.seed.trim() {
+                "" => WorldSeed::default(),
+                seed => WorldSeed::parse(seed),
+            };
+            start_events.send(StartWorld::New(seed));
+        }
+        MenuAction::LoadWorld => {
+            start_events.send(StartWorld::Load);
+        }
+        MenuAction::Settings => menu.open(Screen::Settings),
+        MenuAction::Controls => menu.open(Screen::Controls),
+        MenuAction::Rebind(action) => {
+            menu.rebinding = Some(action);
+            menu.message =
+                "Press a key or a button, Backspace to cancel".to_string();
+        }
+        MenuAction::ResetControls => settings.bindings = Bindings::default(),
+        MenuAction::Back => {
+            let screen = match menu.screen {
+                Some(Screen::Controls) => Screen::Settings,
+                _ => menu.previous.unwrap_or(Screen::Main),
+            };
+            menu.open(screen);
+        }
+        MenuAction::Resume => next_state.set(AppState::InGame),
+        MenuAction::QuitToMenu => next_state.set(AppState::MainMenu),
+        MenuAction::Quit => {
+            exit_events.send(AppExit);
+        }
+        _ => {}
+    }
+}
+
+// Run condition for the systems that must ignore the input being bound
+pub fn is_rebinding(menu: Res<MenuState>) -> bool {
+    menu.rebinding.is_some()
+}
+
+// Binds the next key, mouse button or gamepad button to the action waiting
+// for it
+fn capture_binding(
+    mut menu: ResMut<MenuState>,
+    mut settings: ResMut<Settings>,
+    mut armed: Local<bool>,
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mouse_input: Res<ButtonInput<MouseButton>>,
+    gamepad_input: Res<ButtonInput<GamepadButton>>,
+) {
+    let Some(action) = menu.rebinding else {
+        *armed = false;
+        return;
+    };
+    // The input that started the capture is still pressed on this frame
+    if !*armed {
+        *armed = true;
+        return;
+    }
+
+    if keyboard_input.just_pressed(KeyCode::Backspace) {
+        menu.rebinding = None;
+        menu.message.clear();
+        return;
+    }
+    let binding = keyboard_input
+        .get_just_pressed()
+        .next()
+        .map(|key| Binding::Key(*key))
+        .or_else(|| {
+            let button = mouse_input.get_just_pressed().next()?;
+            Some(Binding::Mouse(*button))
+        })
+        .or_else(|| {
+            let button = gamepad_input.get_just_pressed().next()?;
+            Some(Binding::Gamepad(button.button_type))
+        });
+    if let Some(binding) = binding {
+        let others = settings.bindings.bind(action, binding);
+        menu.rebinding = None;
+        menu.message = if others.is_empty() {
+            String::new()
+        } else {
+            let names = others
+                .iter()
+                .map(|other| other.name())
+                .collect::<Vec<_>>()
+                .join(", ");
+            format!("{} is also used by: {}", binding.name(), names)
+        };
+    }
+}
+
+fn update_menu(
+    menu: Res<MenuState>,
+    settings: Res<Settings>,
+    mut button_query: Query<(&MenuButton, &mut BackgroundColor)>,
+    mut label_query: Query<(&Parent, &mut Text), With<ButtonLabel>>,
+    mut text_query: Query<(&mut Text, &MenuText), Without<ButtonLabel>>,
+) {
+    if !menu.is_changed() && !settings.is_changed() {
+        return;
+    }
+
+    for (button, mut background) in button_query.iter_mut() {
+        *background = if button.index == menu.focus {
+            FOCUSED_COLOR.into()
+        } else {
+            BUTTON_COLOR.into()
+        };
+    }
+    for (parent, mut text) in label_query.iter_mut() {
+        if let Ok((button, _)) = button_query.get(parent.get()) {
+            text.sections[0].value = match button.action {
+                MenuAction::Rebind(action)
+                    if menu.rebinding == Some(action) =>
+                {
+                    format!("{}: ...", action.name())
+                }
+                action => action.label(&settings),
+            };
+        }
+    }
+    for (mut text, menu_text) in text_query.iter_mut() {
+        text.sections[0].value = match menu_text {
+            MenuText::Seed => format!("Seed: {}_", menu.seed),
+            MenuText::Message => menu.message.clone(),
+        };
+    }
+}
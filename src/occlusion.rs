@@ -0,0 +1,288 @@
+use bevy::prelude::*;
+use std::{
+    collections::{HashMap, HashSet},
+    f32::consts::FRAC_PI_8,
+};
+
+use crate::{
+    camera::{CameraRig, CameraSet, CameraView},
+    entities::entity::{vegetation, Resources as EntityResources},
+    physics::Body,
+    player::Player,
+    state::AppState,
+    world::{CutAway, VoxelWorld},
+};
+
+// Distance between the points checked along the line of sight
+const SAMPLE_STEP: f32 = 0.5;
+
+// Components ------------------------------------------------------------------
+
+// Plant standing between the camera and the player
+#[derive(Component, Debug)]
+pub struct Occluding;
+
+// Material of a mesh before it was faded
+#[derive(Component, Debug)]
+struct OriginalMaterial(Handle<StandardMaterial>);
+
+// Resources -------------------------------------------------------------------
+
+#[derive(Resource, Clone, Debug)]
+pub struct OcclusionConfig {
+    // Radius of the cylinder around the line of sight that is cleared
+    pub radius: f32,
+    // Opacity of the plants in the way
+    pub alpha: f32,
+}
+
+impl Default for OcclusionConfig {
+    fn default() -> Self {
+        OcclusionConfig {
+            radius: 1.0,
+            alpha: 0.3,
+        }
+    }
+}
+
+// Translucent copy of each material that was faded, shared by every mesh
+// using it
+#[derive(Resource, Default)]
+struct FadedMaterials(
+    HashMap<AssetId<StandardMaterial>, Handle<StandardMaterial>>,
+);
+
+// Plugin ----------------------------------------------------------------------
+
+pub struct OcclusionPlugin;
+
+impl Plugin for OcclusionPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<OcclusionConfig>()
+            .init_resource::<FadedMaterials>()
+            .add_systems(
+                Update,
+                (
+                    cut_away_blocks,
+                    (find_occluders, restore_occluders, fade_occluders).chain(),
+                )
+                    // The camera has to be at its final place for the frame
+                    .after(CameraSet)
+                    .run_if(in_state(AppState::InGame)),
+            );
+    }
+}
+
+// Helpers ---------------------------------------------------------------------
+
+// Segment going from the player towards the camera
+struct LineOfSight {
+    start: Vec3,
+    direction: Vec3,
+    length: f32,
+}
+
+impl LineOfSight {
+    // Whether `point` is inside the cylinder of `radius` around the segment.
+    // What is behind the player doesn't hide it.
+    fn contains(&self, point: Vec3, radius: f32) -> bool {
+        let distance = (point - self.start).dot(self.direction);
+        if distance <= 0.0 || distance > self.length {
+            return false;
+        }
+        let closest = self.start + self.direction * distance;
+        closest.distance_squared(point) <= radius * radius
+    }
+
+    // Solid cells inside the cylinder, above the feet of the player so the
+    // ground it stands on is kept
+    fn blocks(
+        &self,
+        voxel_world: &VoxelWorld,
+        feet: f32,
+        radius: f32,
+    ) -> HashSet<IVec3> {
+        let mut blocks = HashSet::new();
+        let samples = (self.length / SAMPLE_STEP).ceil() as i32;
+        for sample in 1..=samples {
+            let point =
+                self.start + self.direction * sample as f32 * SAMPLE_STEP;
+            let center = point.round().as_ivec3();
+            for x in -1..=1 {
+                for y in -1..=1 {
+                    for z in -1..=1 {
+                        let cell = center + IVec3::new(x, y, z);
+                        if cell.y as f32 > feet
+                            && self.contains(cell.as_vec3(), radius)
+                            && voxel_world.is_solid(cell)
+                        {
+                            blocks.insert(cell);
+                        }
+                    }
+                }
+            }
+        }
+        blocks
+    }
+}
+
+fn line_of_sight(
+    camera: &GlobalTransform,
+    rig: &CameraRig,
+    transform: &Transform,
+    body: &Body,
+) -> Option<LineOfSight> {
+    let start = transform.translation + Vec3::Y * body.size.y / 2.0;
+    let offset = camera.translation() - start;
+    match rig.view {
+        // The orthographic camera looks along parallel rays
+        CameraView::Isometric => {
+            let direction = camera.back().normalize();
+            Some(LineOfSight {
+                start,
+                direction,
+                length: offset.dot(direction),
+            })
+        }
+        CameraView::ThirdPerson => Some(LineOfSight {
+            start,
+            direction: offset.normalize_or_zero(),
+            length: offset.length(),
+        }),
+        // Nothing is in the way in first person
+        CameraView::FirstPerson => None,
+    }
+}
+
+// Systems ---------------------------------------------------------------------
+
+// Cuts away the blocks that hide the player. Each change meshes chunks again,
+// so the blocks are only looked for once the player or the camera (which
+// keeps moving after the player stopped, or when zooming) entered another
+// cell, or the camera turned.
+fn cut_away_blocks(
+    config: Res<OcclusionConfig>,
+    voxel_world: Res<VoxelWorld>,
+    mut cut_away: ResMut<CutAway>,
+    mut last: Local<Option<(IVec3, IVec3, CameraView, i32)>>,
+    camera_query: Query<(&GlobalTransform, &CameraRig)>,
+    player_query: Query<(&Transform, &Body), With<Player>>,
+) {
+    let (Ok((camera, rig)), Ok((transform, body))) =
+        (camera_query.get_single(), player_query.get_single())
+    else {
+        return;
+    };
+
+    let key = (
+        transform.translation.round().as_ivec3(),
+        camera.translation().round().as_ivec3(),
+        rig.view,
+        (rig.yaw / FRAC_PI_8).round() as i32,
+    );
+    if *last == Some(key) && !voxel_world.is_changed() {
+        return;
+    }
+    *last = Some(key);
+
+    let blocks = line_of_sight(camera, rig, transform, body).map_or_else(
+        HashSet::new,
+        |line| {
+            line.blocks(&voxel_world, transform.translation.y, config.radius)
+        },
+    );
+    if cut_away.blocks != blocks {
+        cut_away.blocks = blocks;
+    }
+}
+
+// Marks the plants that hide the player
+fn find_occluders(
+    mut commands: Commands,
+    config: Res<OcclusionConfig>,
+    resources: Res<EntityResources>,
+    camera_query: Query<(&GlobalTransform, &CameraRig)>,
+    player_query: Query<(&Transform, &Body), With<Player>>,
+    plant_query: Query<(Entity, &vegetation::Entity, Has<Occluding>)>,
+) {
+    let line = camera_query.get_single().ok().and_then(|(camera, rig)| {
+        let (transform, body) = player_query.get_single().ok()?;
+        line_of_sight(camera, rig, transform, body)
+    });
+
+    for (entity, plant, is_occluding) in plant_query.iter() {
+        let occludes = line.as_ref().is_some_and(|line| {
+            // Every cell covered by the model of the plant
+            let height = vegetation::get_height(&plant.variant, &resources);
+            (0..height).any(|y| {
+                let point = (plant.coordinates + IVec3::Y * y).as_vec3();
+                line.contains(point, config.radius)
+            })
+        });
+        if occludes && !is_occluding {
+            commands.entity(entity).insert(Occluding);
+        } else if !occludes && is_occluding {
+            commands.entity(entity).remove::<Occluding>();
+        }
+    }
+}
+
+// Puts back the materials of the plants that are out of the way
+fn restore_occluders(
+    mut commands: Commands,
+    mut removed: RemovedComponents<Occluding>,
+    children_query: Query<&Children>,
+    mut mesh_query: Query<(&mut Handle<StandardMaterial>, &OriginalMaterial)>,
+) {
+    for entity in removed.read() {
+        for child in children_query.iter_descendants(entity) {
+            let Ok((mut material, original)) = mesh_query.get_mut(child) else {
+                continue;
+            };
+            *material = original.0.clone();
+            commands.entity(child).remove::<OriginalMaterial>();
+        }
+    }
+}
+
+// Swaps the materials of the occluding plants for translucent copies
+fn fade_occluders(
+    mut commands: Commands,
+    config: Res<OcclusionConfig>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut faded_materials: ResMut<FadedMaterials>,
+    occluder_query: Query<Entity, With<Occluding>>,
+    children_query: Query<&Children>,
+    mut mesh_query: Query<
+        &mut Handle<StandardMaterial>,
+        Without<OriginalMaterial>,
+    >,
+) {
+    // Checked every frame, the scene of a plant may be spawned after it
+    // started to occlude
+    for entity in occluder_query.iter() {
+        for child in children_query.iter_descendants(entity) {
+            let Ok(mut material) = mesh_query.get_mut(child) else {
+                continue;
+            };
+            let faded = match faded_materials.0.get(&material.id()) {
+                Some(faded) => faded.clone(),
+                None => {
+                    let Some(mut copy) = materials.get(material.id()).cloned()
+                    else {
+                        continue;
+                    };
+                    copy.base_color.set_a(config.alpha);
+                    copy.alpha_mode = AlphaMode::Blend;
+                    let faded = materials.add(copy);
+                    faded_materials.0.insert(material.id(), faded.clone());
+                    faded
+                }
+            };
+            commands
+                .entity(child)
+                .insert(OriginalMaterial(material.clone()));
+            *material = faded;
+        }
+    }
+}
@@ -0,0 +1,320 @@
+use bevy::prelude::*;
+
+use crate::{
+    entities::entity::{vegetation, Resources as EntityResources},
+    state::AppState,
+    world::VoxelWorld,
+};
+
+// Gap kept between a body and the blocks it touches, so that it doesn't count
+// as overlapping them
+const SKIN: f32 = 0.001;
+
+// Longest distance moved at once, shorter than a block so that nothing is
+// skipped at high speed
+const MAX_STEP: f32 = 0.4;
+
+// Plants with this tag block the way, the others can be walked through
+const SOLID_TAG: &str = "solid";
+
+// Components ------------------------------------------------------------------
+
+// Box moved by the physics, the transform of the entity is at the center of
+// its bottom face
+#[derive(Component, Debug)]
+pub struct Body {
+    // Width (on both horizontal axes) and height
+    pub size: Vec2,
+    pub velocity: Vec3,
+    // Whether the body stood on something after its last move
+    pub on_ground: bool,
+    // Sneaking bodies stop at the edges instead of falling off
+    pub sneaking: bool,
+}
+
+impl Body {
+    pub fn new(size: Vec2) -> Body {
+        Body {
+            size,
+            velocity: Vec3::ZERO,
+            on_ground: false,
+            sneaking: false,
+        }
+    }
+
+    pub fn half_extents(&self) -> Vec3 {
+        Vec3::new(self.size.x / 2.0, self.size.y / 2.0, self.size.x / 2.0)
+    }
+}
+
+// Resources -------------------------------------------------------------------
+
+#[derive(Resource, Clone, Debug)]
+pub struct PhysicsConfig {
+    // Downward acceleration, in blocks per second squared
+    pub gravity: f32,
+    pub max_fall_speed: f32,
+    // Highest ledge climbed without jumping
+    pub step_height: f32,
+    pub jump_height: f32,
+}
+
+impl Default for PhysicsConfig {
+    fn default() -> Self {
+        PhysicsConfig {
+            gravity: 25.0,
+            max_fall_speed: 40.0,
+            step_height: 1.05,
+            jump_height: 1.25,
+        }
+    }
+}
+
+impl PhysicsConfig {
+    // Vertical speed needed to reach `jump_height`
+    pub fn jump_speed(&self) -> f32 {
+        (2.0 * self.gravity * self.jump_height).sqrt()
+    }
+}
+
+// Plugin ----------------------------------------------------------------------
+
+// Systems setting the velocity of the bodies run before this set
+#[derive(SystemSet, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct PhysicsSet;
+
+pub struct PhysicsPlugin;
+
+impl Plugin for PhysicsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PhysicsConfig>().add_systems(
+            Update,
+            move_bodies
+                .in_set(PhysicsSet)
+                .run_if(in_state(AppState::InGame)),
+        );
+    }
+}
+
+// Helpers ---------------------------------------------------------------------
+
+// Solid cells of the world
+pub struct Obstacles<'a> {
+    pub voxel_world: &'a VoxelWorld,
+    pub resources: &'a EntityResources,
+}
+
+impl Obstacles<'_> {
+    pub fn is_solid(&self, coordinates: IVec3) -> bool {
+        self.voxel_world.is_solid(coordinates)
+            || self.is_solid_plant(coordinates)
+    }
+
+    // Plants fill the cells covered by their model, not only their own one
+    pub fn is_solid_plant(&self, coordinates: IVec3) -> bool {
+        (0..vegetation::max_height(self.resources)).any(|below| {
+            self.voxel_world
+                .get_vegetation(coordinates - IVec3::Y * below)
+                .is_some_and(|variant| {
+                    vegetation::has_tag(&variant, SOLID_TAG, self.resources)
+                        && vegetation::get_height(&variant, self.resources)
+                            > below
+                })
+        })
+    }
+
+    // Cells overlapped by a box of `half_extents` with its bottom center at
+    // `position`. Blocks are centered on their coordinates.
+    pub fn cells(
+        &self,
+        position: Vec3,
+        half_extents: Vec3,
+    ) -> impl Iterator<Item = IVec3> {
+        let center = position + Vec3::Y * half_extents.y;
+        let min = (center - half_extents + 0.5).floor().as_ivec3();
+        let max = (center + half_extents + 0.5).floor().as_ivec3();
+
+        (min.x..=max.x).flat_map(move |x| {
+            (min.y..=max.y).flat_map(move |y| {
+                (min.z..=max.z).map(move |z| IVec3::new(x, y, z))
+            })
+        })
+    }
+
+    fn collides(&self, position: Vec3, half_extents: Vec3) -> bool {
+        self.cells(position, half_extents)
+            .any(|cell| self.is_solid(cell))
+    }
+
+    // Plants can't be climbed like a ledge, even the short ones
+    fn touches_plant(&self, position: Vec3, half_extents: Vec3) -> bool {
+        self.cells(position, half_extents)
+            .any(|cell| self.is_solid_plant(cell))
+    }
+
+    // Moves the box along one axis, stopping against the first solid cell.
+    // Returns false when it was stopped.
+    fn move_axis(
+        &self,
+        position: &mut Vec3,
+        half_extents: Vec3,
+        axis: usize,
+        distance: f32,
+    ) -> bool {
+        let mut target = *position;
+        target[axis] += distance;
+        if !self.collides(target, half_extents) {
+            *position = target;
+            return true;
+        }
+
+        // Stop right against the face of the cell that was entered
+        let offset = if axis == 1 {
+            Vec3::new(0.0, half_extents.y, 0.0)
+        } else {
+            Vec3::ZERO
+        };
+        let center = target[axis] + offset[axis];
+        if distance > 0.0 {
+            let face = (center + half_extents[axis] + 0.5).floor() - 0.5;
+            position[axis] = face - half_extents[axis] - offset[axis] - SKIN;
+        } else {
+            let face = (center - half_extents[axis] + 0.5).floor() + 0.5;
+            position[axis] = face + half_extents[axis] - offset[axis] + SKIN;
+        }
+        false
+    }
+}
+
+// Systems ---------------------------------------------------------------------
+
+fn move_bodies(
+    time: Res<Time>,
+    config: Res<PhysicsConfig>,
+    voxel_world: Res<VoxelWorld>,
+    resources: Res<EntityResources>,
+    mut query: Query<(&mut Transform, &mut Body)>,
+) {
+    let obstacles = Obstacles {
+        voxel_world: &voxel_world,
+        resources: &resources,
+    };
+    let delta = time.delta_seconds();
+
+    for (mut transform, mut body) in query.iter_mut() {
+        let half_extents = body.half_extents();
+        let mut position = transform.translation;
+
+        // Bodies wait for the ground under them to be generated
+        if !voxel_world.is_loaded(position.round().as_ivec3()) {
+            continue;
+        }
+
+        // A block placed (or a body teleported) inside it pushes it up
+        for _ in 0..16 {
+            if !obstacles.collides(position, half_extents) {
+                break;
+            }
+            position.y = (position.y + 0.5).floor() + 0.5 + SKIN;
+        }
+
+        body.velocity.y = (body.velocity.y - config.gravity * delta)
+            .max(-config.max_fall_speed);
+
+        let movement = body.velocity * delta;
+        let steps = (movement.length() / MAX_STEP).ceil().max(1.0);
+        let step = movement / steps;
+
+        let mut on_ground = false;
+        for _ in 0..steps as usize {
+            // Vertical move first, so that the ground is known when walking
+            if !obstacles.move_axis(&mut position, half_extents, 1, step.y) {
+                if step.y < 0.0 {
+                    on_ground = true;
+                }
+                body.velocity.y = 0.0;
+            }
+
+            for axis in [0, 2] {
+                if step[axis] == 0.0 {
+                    continue;
+                }
+                let start = position;
+                if obstacles.move_axis(
+                    &mut position,
+                    half_extents,
+                    axis,
+                    step[axis],
+                ) {
+                    // Nothing left under the body, it's walking off an edge
+                    if body.sneaking
+                        && on_ground
+                        && !obstacles
+                            .collides(position - Vec3::Y * 0.1, half_extents)
+                    {
+                        position = start;
+                    }
+                    continue;
+                }
+                if !on_ground {
+                    continue;
+                }
+
+                // Blocked while walking, try to climb onto the ledge
+                let mut blocked = start;
+                blocked[axis] += step[axis];
+                let mut raised = start + Vec3::Y * config.step_height;
+                if obstacles.touches_plant(blocked, half_extents)
+                    || obstacles.collides(raised, half_extents)
+                    || !obstacles.move_axis(
+                        &mut raised,
+                        half_extents,
+                        axis,
+                        step[axis],
+                    )
+                {
+                    continue;
+                }
+                obstacles.move_axis(
+                    &mut raised,
+                    half_extents,
+                    1,
+                    -config.step_height,
+                );
+                position = raised;
+            }
+        }
+
+        body.on_ground = on_ground;
+        transform.translation = position;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn body_cells_include_the_neighbors_it_overlaps() {
+        let voxel_world = VoxelWorld::default();
+        let resources = EntityResources::default();
+        let obstacles = Obstacles {
+            voxel_world: &voxel_world,
+            resources: &resources,
+        };
+        let half_extents = Body::new(Vec2::new(0.6, 1.8)).half_extents();
+
+        // Standing on the block at y = 0, close to the +x edge of its cell
+        let position = Vec3::new(0.4, 0.5 + SKIN, 0.0);
+        let cells = obstacles.cells(position, half_extents).collect::<Vec<_>>();
+        for cell in [
+            IVec3::new(0, 1, 0),
+            IVec3::new(1, 1, 0),
+            IVec3::new(1, 2, 0),
+        ] {
+            assert!(cells.contains(&cell));
+        }
+        assert!(!cells.contains(&IVec3::new(-1, 1, 0)));
+        assert!(!cells.contains(&IVec3::new(0, 0, 0)));
+    }
+}
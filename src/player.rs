@@ -1,99 +1,320 @@
 use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
 
 use crate::{
     animation::Animated,
+    camera::{CameraRig, CameraView},
     entities::entity::{character, Resources as EntityResources},
-    input::InputData,
+    input::{ground_direction, Action, Actions, InputData},
+    physics::{Body, PhysicsConfig, PhysicsSet},
+    save::LoadedPlayer,
+    settings::Settings,
+    state::AppState,
+    world::Terrain,
 };
 
+// Core ------------------------------------------------------------------------
+
+// What the movement keys are relative to
+#[derive(
+    Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq,
+)]
+pub enum MovementMode {
+    // Forward is where the player looks
+    #[default]
+    Facing,
+    // Forward is up on the screen
+    Camera,
+}
+
+#[derive(Component, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Stance {
+    #[default]
+    Walk,
+    Sprint,
+    // Slower, and doesn't fall off the edges of the blocks
+    Crouch,
+}
+
+impl Stance {
+    fn speed(&self) -> f32 {
+        match self {
+            Stance::Walk => WALK_SPEED,
+            Stance::Sprint => SPRINT_SPEED,
+            Stance::Crouch => CROUCH_SPEED,
+        }
+    }
+}
+
+impl MovementMode {
+    pub fn name(&self) -> &'static str {
+        match self {
+            MovementMode::Facing => "Facing",
+            MovementMode::Camera => "Camera",
+        }
+    }
+}
+
+// Speeds of each stance, in blocks per second
+const WALK_SPEED: f32 = 5.5;
+const SPRINT_SPEED: f32 = 9.0;
+const CROUCH_SPEED: f32 = 2.5;
+
+// Stamina spent and recovered per second
+const STAMINA_DRAIN: f32 = 25.0;
+const STAMINA_REGENERATION: f32 = 15.0;
+// Part of the stamina to recover before sprinting again once it's exhausted
+const STAMINA_RECOVERY: f32 = 0.25;
+
+// Size of the collision box, the model is about as wide as a block
+const SIZE: Vec2 = Vec2::new(0.6, 1.8);
+
 // Component -------------------------------------------------------------------
 
 #[derive(Component)]
-struct Player;
+pub struct Player;
+
+// Resources -------------------------------------------------------------------
+
+// Spent while sprinting, regenerates otherwise
+#[derive(Resource, Debug)]
+pub struct Stamina {
+    pub value: f32,
+    pub max: f32,
+    // Set when running out, until enough is recovered
+    exhausted: bool,
+}
+
+impl Default for Stamina {
+    fn default() -> Self {
+        Stamina {
+            value: 100.0,
+            max: 100.0,
+            exhausted: false,
+        }
+    }
+}
+
+impl Stamina {
+    fn update(&mut self, is_sprinting: bool, delta: f32) {
+        if is_sprinting {
+            self.value = (self.value - STAMINA_DRAIN * delta).max(0.0);
+            if self.value == 0.0 {
+                self.exhausted = true;
+            }
+        } else {
+            self.value =
+                (self.value + STAMINA_REGENERATION * delta).min(self.max);
+            if self.value >= self.max * STAMINA_RECOVERY {
+                self.exhausted = false;
+            }
+        }
+    }
+}
 
 // Plugin ----------------------------------------------------------------------
 
+// Systems setting the velocity of the player from the controls
+#[derive(SystemSet, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct PlayerSet;
+
 pub struct PlayerPlugin;
 
 impl Plugin for PlayerPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(PostStartup, initialize_player)
-            .add_systems(Update, player_movement);
+        // Resuming from the pause menu also enters `InGame`, the player is
+        // only spawned when coming from the loading screen
+        app.init_resource::<Stamina>()
+            .add_systems(
+                OnTransition {
+                    from: AppState::Loading,
+                    to: AppState::InGame,
+                },
+                initialize_player,
+            )
+            .add_systems(OnEnter(AppState::MainMenu), despawn_player)
+            .add_systems(
+                Update,
+                (update_stance, player_movement)
+                    .chain()
+                    .in_set(PlayerSet)
+                    .before(PhysicsSet)
+                    .run_if(in_state(AppState::InGame)),
+            );
     }
 }
 
 // Systems ---------------------------------------------------------------------
 
-fn initialize_player(mut commands: Commands, resources: Res<EntityResources>) {
-    character::spawn(
+fn initialize_player(
+    mut commands: Commands,
+    resources: Res<EntityResources>,
+    terrain: Res<Terrain>,
+    loaded_player: Option<Res<LoadedPlayer>>,
+) {
+    commands.insert_resource(Stamina::default());
+    let player = character::spawn(
         &mut commands,
         &resources,
         character::Entity {
-            coordinates: IVec3::new(0, 0, 0),
-            variant: character::Variant::Player,
+            // Stand on top of the surface block at the origin
+            coordinates: IVec3::new(0, terrain.height(0, 0), 0),
+            variant: character::Variant::PLAYER,
         },
-        Player,
+        (Player, Body::new(SIZE), Stance::default()),
     );
+
+    // A loaded world puts the player back where it was saved, before the
+    // physics or the camera ever see the spawn position
+    if let (Some(player), Some(loaded_player)) = (player, loaded_player) {
+        let saved = loaded_player.0;
+        commands
+            .entity(player)
+            .add(move |mut entity: EntityWorldMut| {
+                if let Some(mut transform) = entity.get_mut::<Transform>() {
+                    transform.translation = saved.translation;
+                    transform.rotation = saved.rotation;
+                }
+            });
+        commands.remove_resource::<LoadedPlayer>();
+    }
 }
 
-fn player_movement(
+fn despawn_player(mut commands: Commands, query: Query<Entity, With<Player>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+fn update_stance(
     time: Res<Time>,
+    actions: Res<Actions>,
+    mut stamina: ResMut<Stamina>,
+    mut query: Query<(&mut Stance, &Body), With<Player>>,
+) {
+    for (mut stance, body) in query.iter_mut() {
+        let is_moving = body.velocity.xz() != Vec2::ZERO;
+        *stance = if actions.pressed(Action::Crouch) {
+            Stance::Crouch
+        } else if actions.pressed(Action::Sprint)
+            && is_moving
+            && !stamina.exhausted
+        {
+            Stance::Sprint
+        } else {
+            Stance::Walk
+        };
+        stamina.update(*stance == Stance::Sprint, time.delta_seconds());
+    }
+}
+
+fn player_movement(
     resources: Res<EntityResources>,
+    physics_config: Res<PhysicsConfig>,
     input_data: Res<InputData>,
-    mut query: Query<(&mut Transform, &mut Animated), With<Player>>,
+    actions: Res<Actions>,
+    settings: Res<Settings>,
+    camera_query: Query<(&GlobalTransform, &CameraRig)>,
+    mut query: Query<
+        (&mut Transform, &mut Animated, &mut Body, &Stance),
+        With<Player>,
+    >,
 ) {
-    for (mut transform, mut animated) in query.iter_mut() {
-        // Rotate the player to look at the mouse position
-        let difference =
-            input_data.mouse_position.coordinates - transform.translation;
-        let angle = f32::atan2(difference.x, difference.z);
-        transform.rotation = Quat::from_rotation_y(angle);
-
-        // Move the player based on pressed keys
-        let rotation = transform.rotation.clone();
-
-        let mut translation_offset = Vec3::ZERO;
-        let mut is_walking = false;
-
-        match input_data.pressed_keys.iter().next() {
-            Some(key) => {
-                is_walking = true;
-                match key {
-                    KeyCode::KeyW => {
-                        translation_offset += rotation.mul_vec3(Vec3::Z)
-                    }
-                    KeyCode::KeyS => {
-                        translation_offset -= rotation.mul_vec3(Vec3::Z)
-                    }
-                    KeyCode::KeyA => {
-                        translation_offset += rotation.mul_vec3(Vec3::X)
-                    }
-                    KeyCode::KeyD => {
-                        translation_offset -= rotation.mul_vec3(Vec3::X)
-                    }
-                    _ => {}
-                }
+    for (mut transform, mut animated, mut body, stance) in query.iter_mut() {
+        // Rotate the player to look at the mouse position, or along the right
+        // stick (then the walking direction) when playing with a gamepad. In
+        // first person the camera turns the player instead.
+        let camera = camera_query.get_single().ok();
+        let gamepad = &input_data.gamepad;
+        let look = if camera
+            .is_some_and(|(_, rig)| rig.view == CameraView::FirstPerson)
+        {
+            None
+        } else if gamepad.in_use {
+            Some(gamepad.aim.unwrap_or(gamepad.movement))
+        } else {
+            input_data
+                .mouse_position
+                .point_at_height(transform.translation.y)
+                .map(|point| point - transform.translation)
+        };
+        if let Some(direction) =
+            look.filter(|direction| direction.xz() != Vec2::ZERO)
+        {
+            let angle = f32::atan2(direction.x, direction.z);
+            transform.rotation = Quat::from_rotation_y(angle);
+        }
+
+        // Move the player based on the pressed actions, all of them are added
+        // together (x to the right and y forward) and diagonals are not faster
+        let mut direction = Vec2::ZERO;
+        for (action, offset) in [
+            (Action::MoveForward, Vec2::Y),
+            (Action::MoveBack, Vec2::NEG_Y),
+            (Action::StrafeLeft, Vec2::NEG_X),
+            (Action::StrafeRight, Vec2::X),
+        ] {
+            if actions.pressed(action) {
+                direction += offset;
             }
-            _ => {}
         }
+        let direction = direction.normalize_or_zero();
 
-        // Update translation
-        let speed_offset = time.delta_seconds() * 5.5;
-        transform.translation += translation_offset * speed_offset;
+        let mut translation_offset = match settings.movement_mode {
+            MovementMode::Facing => transform.rotation.mul_vec3(Vec3::new(
+                -direction.x,
+                0.0,
+                direction.y,
+            )),
+            MovementMode::Camera => {
+                camera.map_or(Vec3::ZERO, |(camera_transform, _)| {
+                    ground_direction(camera_transform, direction)
+                })
+            }
+        };
 
-        // Play the walk animation
-        animated.handle = if is_walking {
-            character::get_animation(
-                &character::Variant::Player,
-                &character::Animation::Walk,
-                &resources,
-            )
-        } else {
+        // The left stick walks towards where it points on the screen, slower
+        // when it is only pushed a little
+        if translation_offset == Vec3::ZERO {
+            translation_offset = gamepad.movement;
+        }
+        let is_walking = translation_offset != Vec3::ZERO;
+
+        // The physics moves the player, stopping it against the blocks
+        body.velocity.x = translation_offset.x * stance.speed();
+        body.velocity.z = translation_offset.z * stance.speed();
+        body.sneaking = *stance == Stance::Crouch;
+        if actions.just_pressed(Action::Jump) && body.on_ground {
+            body.velocity.y = physics_config.jump_speed();
+        }
+
+        // Play the animation of the stance, the walk is slowed down when
+        // crouching if the model has no sneak animation
+        let get_animation = |animation| {
             character::get_animation(
-                &character::Variant::Player,
-                &character::Animation::Idle,
+                &character::Variant::PLAYER,
+                &animation,
                 &resources,
             )
         };
+        let (handle, speed) = match (is_walking, stance) {
+            (false, _) => (get_animation(character::Animation::IDLE), 1.0),
+            (true, Stance::Walk) => {
+                (get_animation(character::Animation::WALK), 1.0)
+            }
+            (true, Stance::Sprint) => {
+                (get_animation(character::Animation::RUN), 1.0)
+            }
+            (true, Stance::Crouch) => {
+                match get_animation(character::Animation::SNEAK) {
+                    Some(handle) => (Some(handle), 1.0),
+                    None => (get_animation(character::Animation::WALK), 0.5),
+                }
+            }
+        };
+        if let Some(handle) = handle {
+            animated.handle = handle;
+            animated.speed = speed;
+        }
     }
 }
@@ -0,0 +1,399 @@
+use bevy::{app::AppExit, prelude::*};
+use serde::{Deserialize, Serialize};
+use std::{fs, path::PathBuf};
+
+use crate::{
+    entities::entity::{block, vegetation},
+    light::TimeOfDay,
+    player::Player,
+    state::AppState,
+    world::{ChunkData, VoxelWorld, WorldSeed},
+};
+
+// Bumped whenever the format changes, saves from other versions are refused
+// instead of being loaded wrongly
+pub const SAVE_VERSION: u32 = 2;
+
+// Format ----------------------------------------------------------------------
+
+// Only the chunks edited by the player are stored, the others are generated
+// again from the seed
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct SaveData {
+    pub version: u32,
+    pub seed: u64,
+    pub time_of_day: f32,
+    pub player: PlayerData,
+    pub chunks: Vec<ChunkSave>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct PlayerData {
+    pub translation: [f32; 3],
+    pub rotation: [f32; 4],
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct ChunkSave {
+    pub coordinates: [i32; 2],
+    pub blocks: Vec<([i32; 3], block::Variant)>,
+    pub vegetation: Vec<([i32; 3], vegetation::Variant)>,
+}
+
+impl ChunkSave {
+    fn from_data(coordinates: IVec2, data: &ChunkData) -> ChunkSave {
+        let mut blocks = data
+            .blocks
+            .iter()
+            .map(|(coordinates, variant)| (coordinates.to_array(), *variant))
+            .collect::<Vec<_>>();
+        let mut vegetation = data
+            .vegetation
+            .iter()
+            .map(|(coordinates, variant)| (coordinates.to_array(), *variant))
+            .collect::<Vec<_>>();
+
+        // Sorted so that saving the same world twice gives the same file
+        blocks.sort_by_key(|(coordinates, _)| *coordinates);
+        vegetation.sort_by_key(|(coordinates, _)| *coordinates);
+
+        ChunkSave {
+            coordinates: coordinates.to_array(),
+            blocks,
+            vegetation,
+        }
+    }
+
+    fn into_data(self) -> (IVec2, ChunkData) {
+        let data = ChunkData {
+            blocks: self
+                .blocks
+                .into_iter()
+                .map(|(coordinates, variant)| {
+                    (IVec3::from_array(coordinates), variant)
+                })
+                .collect(),
+            vegetation: self
+                .vegetation
+                .into_iter()
+                .map(|(coordinates, variant)| {
+                    (IVec3::from_array(coordinates), variant)
+                })
+                .collect(),
+            modified: true,
+        };
+        (IVec2::from_array(self.coordinates), data)
+    }
+}
+
+impl SaveData {
+    pub fn to_ron(&self) -> Result<String, String> {
+        ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|error| error.to_string())
+    }
+
+    pub fn from_ron(content: &str) -> Result<SaveData, String> {
+        let data = ron::from_str::<SaveData>(content)
+            .map_err(|error| error.to_string())?;
+        if data.version != SAVE_VERSION {
+            return Err(format!(
+                "Unsupported save version {} (expected {})",
+                data.version, SAVE_VERSION
+            ));
+        }
+        Ok(data)
+    }
+}
+
+// Resources -------------------------------------------------------------------
+
+#[derive(Resource, Clone, Debug)]
+pub struct SaveConfig {
+    pub path: PathBuf,
+    // Seconds between two automatic saves
+    pub autosave_interval: f32,
+    // When disabled the world is never written to disk
+    pub enabled: bool,
+}
+
+impl Default for SaveConfig {
+    fn default() -> Self {
+        SaveConfig {
+            path: PathBuf::from("saves/world.ron"),
+            autosave_interval: 60.0,
+            enabled: true,
+        }
+    }
+}
+
+#[derive(Resource)]
+struct AutosaveTimer(Timer);
+
+// Player transform read from the save, the player is spawned with it
+#[derive(Resource)]
+pub struct LoadedPlayer(pub Transform);
+
+// Events ----------------------------------------------------------------------
+
+// Sent by the main menu to start playing, either on a new world or on the one
+// stored in the save file
+#[derive(Event, Clone, Copy, Debug)]
+pub enum StartWorld {
+    New(WorldSeed),
+    Load,
+}
+
+// Sent when the save file can't be loaded, with the reason
+#[derive(Event, Clone, Debug)]
+pub struct LoadFailed(pub String);
+
+// Plugin ----------------------------------------------------------------------
+
+pub struct SavePlugin;
+
+impl Plugin for SavePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SaveConfig>()
+            .add_event::<StartWorld>()
+            .add_event::<LoadFailed>()
+            // The seed has to be known before the terrain is set up, which
+            // happens when entering the loading state
+            .add_systems(
+                Update,
+                start_world.run_if(in_state(AppState::MainMenu)),
+            )
+            .add_systems(Update, autosave.run_if(in_state(AppState::InGame)))
+            .add_systems(
+                OnTransition {
+                    from: AppState::Paused,
+                    to: AppState::MainMenu,
+                },
+                save_on_leave,
+            )
+            .add_systems(Last, save_on_exit);
+    }
+}
+
+// Helpers ---------------------------------------------------------------------
+
+fn collect(
+    seed: &WorldSeed,
+    time_of_day: &TimeOfDay,
+    voxel_world: &VoxelWorld,
+    player_transform: &Transform,
+) -> SaveData {
+    let mut chunks = voxel_world
+        .modified_chunks()
+        .map(|(coordinates, data)| ChunkSave::from_data(*coordinates, data))
+        .collect::<Vec<_>>();
+    chunks.sort_by_key(|chunk| chunk.coordinates);
+
+    SaveData {
+        version: SAVE_VERSION,
+        seed: seed.0,
+        time_of_day: time_of_day.0,
+        player: PlayerData {
+            translation: player_transform.translation.to_array(),
+            rotation: player_transform.rotation.to_array(),
+        },
+        chunks,
+    }
+}
+
+fn write(config: &SaveConfig, data: &SaveData) {
+    if !config.enabled {
+        return;
+    }
+
+    let result = data.to_ron().and_then(|content| {
+        if let Some(directory) = config.path.parent() {
+            fs::create_dir_all(directory).map_err(|error| error.to_string())?;
+        }
+        fs::write(&config.path, content).map_err(|error| error.to_string())
+    });
+
+    match result {
+        Ok(_) => info!("World saved to {}", config.path.display()),
+        Err(error) => error!(
+            "Unable to save the world to {}: {}",
+            config.path.display(),
+            error
+        ),
+    }
+}
+
+// Systems ---------------------------------------------------------------------
+
+fn start_world(
+    mut commands: Commands,
+    mut events: EventReader<StartWorld>,
+    mut failures: EventWriter<LoadFailed>,
+    mut next_state: ResMut<NextState<AppState>>,
+    config: Res<SaveConfig>,
+) {
+    let Some(event) = events.read().last().copied() else {
+        return;
+    };
+
+    let mut voxel_world = VoxelWorld::default();
+    match event {
+        StartWorld::New(seed) => {
+            info!("Starting a new world with seed {}", seed.0);
+            commands.insert_resource(seed);
+            commands.insert_resource(TimeOfDay::default());
+            commands.remove_resource::<LoadedPlayer>();
+        }
+        StartWorld::Load => {
+            let data = fs::read_to_string(&config.path)
+                .map_err(|error| error.to_string())
+                .and_then(|content| SaveData::from_ron(&content));
+            let data = match data {
+                Ok(data) => data,
+                Err(error) => {
+                    error!(
+                        "Unable to load the world from {}: {}",
+                        config.path.display(),
+                        error
+                    );
+                    failures.send(LoadFailed(error));
+                    return;
+                }
+            };
+
+            info!("World loaded from {}", config.path.display());
+            commands.insert_resource(WorldSeed(data.seed));
+            commands.insert_resource(TimeOfDay(data.time_of_day));
+            commands.insert_resource(LoadedPlayer(Transform {
+                translation: Vec3::from_array(data.player.translation),
+                rotation: Quat::from_array(data.player.rotation),
+                ..default()
+            }));
+            for chunk in data.chunks {
+                let (coordinates, data) = chunk.into_data();
+                voxel_world.insert_chunk(coordinates, data);
+            }
+        }
+    }
+
+    commands.insert_resource(voxel_world);
+    commands.insert_resource(AutosaveTimer(Timer::from_seconds(
+        config.autosave_interval,
+        TimerMode::Repeating,
+    )));
+    next_state.set(AppState::Loading);
+}
+
+fn autosave(
+    time: Res<Time>,
+    mut timer: ResMut<AutosaveTimer>,
+    config: Res<SaveConfig>,
+    seed: Res<WorldSeed>,
+    time_of_day: Res<TimeOfDay>,
+    voxel_world: Res<VoxelWorld>,
+    player_query: Query<&Transform, With<Player>>,
+) {
+    if !timer.0.tick(time.delta()).just_finished() {
+        return;
+    }
+    if let Ok(player_transform) = player_query.get_single() {
+        write(
+            &config,
+            &collect(&seed, &time_of_day, &voxel_world, player_transform),
+        );
+    }
+}
+
+// Quitting to the main menu unloads the world, which is saved first
+fn save_on_leave(
+    config: Res<SaveConfig>,
+    seed: Res<WorldSeed>,
+    time_of_day: Res<TimeOfDay>,
+    voxel_world: Res<VoxelWorld>,
+    player_query: Query<&Transform, With<Player>>,
+) {
+    if let Ok(player_transform) = player_query.get_single() {
+        write(
+            &config,
+            &collect(&seed, &time_of_day, &voxel_world, player_transform),
+        );
+    }
+}
+
+fn save_on_exit(
+    mut exit_events: EventReader<AppExit>,
+    config: Res<SaveConfig>,
+    seed: Res<WorldSeed>,
+    time_of_day: Res<TimeOfDay>,
+    voxel_world: Res<VoxelWorld>,
+    player_query: Query<&Transform, With<Player>>,
+) {
+    if exit_events.read().next().is_none() {
+        return;
+    }
+    if let Ok(player_transform) = player_query.get_single() {
+        write(
+            &config,
+            &collect(&seed, &time_of_day, &voxel_world, player_transform),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entities::Id;
+
+    fn chunk_data() -> ChunkData {
+        let mut data = ChunkData {
+            modified: true,
+            ..default()
+        };
+        data.blocks
+            .insert(IVec3::new(1, 0, 2), block::Variant(Id::new("grass")));
+        data.blocks
+            .insert(IVec3::new(1, -1, 2), block::Variant(Id::new("dirt")));
+        data.vegetation
+            .insert(IVec3::new(1, 1, 2), vegetation::Variant(Id::new("tree")));
+        data
+    }
+
+    fn save_data() -> SaveData {
+        SaveData {
+            version: SAVE_VERSION,
+            seed: 42,
+            time_of_day: 0.25,
+            player: PlayerData {
+                translation: [1.0, 2.5, -3.0],
+                rotation: Quat::from_rotation_y(1.0).to_array(),
+            },
+            chunks: vec![ChunkSave::from_data(
+                IVec2::new(0, -1),
+                &chunk_data(),
+            )],
+        }
+    }
+
+    #[test]
+    fn save_data_round_trip() {
+        let data = save_data();
+        let content = data.to_ron().unwrap();
+        assert_eq!(SaveData::from_ron(&content), Ok(data));
+    }
+
+    #[test]
+    fn chunk_round_trip() {
+        let coordinates = IVec2::new(0, -1);
+        let save = ChunkSave::from_data(coordinates, &chunk_data());
+        assert_eq!(save.into_data(), (coordinates, chunk_data()));
+    }
+
+    #[test]
+    fn other_versions_are_refused() {
+        let data = SaveData {
+            version: SAVE_VERSION + 1,
+            ..save_data()
+        };
+        let content = data.to_ron().unwrap();
+        assert!(SaveData::from_ron(&content).is_err());
+    }
+}
@@ -0,0 +1,280 @@
+use bevy::{
+    audio::{GlobalVolume, Volume},
+    prelude::*,
+    window::{
+        PresentMode, PrimaryWindow, WindowLevel, WindowMode, WindowPosition,
+        WindowResolution,
+    },
+};
+use serde::{Deserialize, Serialize};
+use std::{fs, path::PathBuf};
+
+use crate::{
+    input::Bindings, light::SunMoonLight, player::MovementMode,
+    world::ChunkConfig,
+};
+
+const TITLE: &str = "Genesys Ultimate";
+
+// Folder created inside the user configuration directory
+const CONFIG_FOLDER: &str = "genesys-ultimate";
+const CONFIG_FILE: &str = "settings.ron";
+
+// Core ------------------------------------------------------------------------
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DisplayMode {
+    Windowed,
+    Borderless,
+    Fullscreen,
+}
+
+impl DisplayMode {
+    fn window_mode(&self) -> WindowMode {
+        match self {
+            DisplayMode::Windowed => WindowMode::Windowed,
+            DisplayMode::Borderless => WindowMode::BorderlessFullscreen,
+            DisplayMode::Fullscreen => WindowMode::Fullscreen,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct WindowSettings {
+    pub mode: DisplayMode,
+    pub resolution: [f32; 2],
+    // Position of the top left corner, `None` lets the system decide
+    pub position: Option<[i32; 2]>,
+    pub always_on_top: bool,
+    pub vsync: bool,
+}
+
+impl Default for WindowSettings {
+    fn default() -> Self {
+        WindowSettings {
+            mode: DisplayMode::Windowed,
+            resolution: [1075.0, 610.0],
+            position: None,
+            always_on_top: false,
+            vsync: true,
+        }
+    }
+}
+
+// Resources -------------------------------------------------------------------
+
+// User preferences, loaded from the config file on startup and written back
+// (and applied) whenever they change
+#[derive(Resource, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct Settings {
+    pub window: WindowSettings,
+    pub shadows: bool,
+    // Distance (in chunks) around the player that is kept loaded
+    pub view_distance: i32,
+    pub bindings: Bindings,
+    pub movement_mode: MovementMode,
+    // Stick deflection (from 0.0 to 1.0) below which the stick is ignored
+    pub dead_zone: f32,
+    // Master volume, from 0.0 (muted) to 1.0
+    pub volume: f32,
+    // Display mode given on the command line, only used for this run and
+    // never written to the file
+    #[serde(skip)]
+    pub mode_override: Option<DisplayMode>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            window: WindowSettings::default(),
+            shadows: true,
+            view_distance: 2,
+            bindings: Bindings::default(),
+            movement_mode: MovementMode::Facing,
+            dead_zone: 0.15,
+            volume: 1.0,
+            mode_override: None,
+        }
+    }
+}
+
+impl Settings {
+    // `$XDG_CONFIG_HOME` (or `~/.config`) on Unix, `%APPDATA%` on Windows,
+    // the working directory when none of them is set
+    pub fn path() -> PathBuf {
+        let directory = std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| {
+                std::env::var_os("HOME")
+                    .map(|home| PathBuf::from(home).join(".config"))
+            })
+            .or_else(|| std::env::var_os("APPDATA").map(PathBuf::from));
+
+        match directory {
+            Some(directory) => directory.join(CONFIG_FOLDER).join(CONFIG_FILE),
+            None => PathBuf::from(CONFIG_FILE),
+        }
+    }
+
+    // Falls back to the defaults when the file is missing or invalid, so a
+    // broken config never prevents the game from starting. An invalid file is
+    // kept aside instead of being overwritten by the next save.
+    pub fn load() -> Settings {
+        let path = Settings::path();
+        let Ok(content) = fs::read_to_string(&path) else {
+            return Settings::default();
+        };
+        match ron::from_str::<Settings>(&content) {
+            Ok(mut settings) => {
+                settings.bindings.complete();
+                settings
+            }
+            Err(error) => {
+                // Called before the log plugin is set up
+                eprintln!("Invalid settings in {}: {}", path.display(), error);
+                let backup = path.with_extension("ron.bak");
+                match fs::rename(&path, &backup) {
+                    Ok(()) => eprintln!("Moved to {}", backup.display()),
+                    Err(error) => eprintln!(
+                        "Unable to move it to {}: {}",
+                        backup.display(),
+                        error
+                    ),
+                }
+                Settings::default()
+            }
+        }
+    }
+
+    pub fn save(&self) {
+        let path = Settings::path();
+        let result = ron::ser::to_string_pretty(self, default())
+            .map_err(|error| error.to_string())
+            .and_then(|content| {
+                if let Some(directory) = path.parent() {
+                    fs::create_dir_all(directory)
+                        .map_err(|error| error.to_string())?;
+                }
+                fs::write(&path, content).map_err(|error| error.to_string())
+            });
+
+        if let Err(error) = result {
+            error!(
+                "Unable to save the settings to {}: {}",
+                path.display(),
+                error
+            );
+        }
+    }
+
+    pub fn display_mode(&self) -> DisplayMode {
+        self.mode_override.unwrap_or(self.window.mode)
+    }
+
+    // Window settings in use, with the command line override applied
+    fn window_settings(&self) -> WindowSettings {
+        WindowSettings {
+            mode: self.display_mode(),
+            ..self.window.clone()
+        }
+    }
+
+    // Primary window described by these settings
+    pub fn window(&self) -> Window {
+        let mut window = Window {
+            title: TITLE.into(),
+            ..default()
+        };
+        self.apply_window(&mut window);
+        window
+    }
+
+    fn apply_window(&self, window: &mut Window) {
+        let settings = &self.window_settings();
+        window.mode = settings.mode.window_mode();
+        window.resolution = WindowResolution::new(
+            settings.resolution[0],
+            settings.resolution[1],
+        );
+        window.position = match settings.position {
+            Some([x, y]) => WindowPosition::At(IVec2::new(x, y)),
+            None => WindowPosition::Automatic,
+        };
+        window.window_level = if settings.always_on_top {
+            WindowLevel::AlwaysOnTop
+        } else {
+            WindowLevel::Normal
+        };
+        window.present_mode = if settings.vsync {
+            PresentMode::AutoVsync
+        } else {
+            PresentMode::AutoNoVsync
+        };
+    }
+}
+
+// Plugin ----------------------------------------------------------------------
+
+pub struct SettingsPlugin;
+
+impl Plugin for SettingsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Settings>()
+            .add_systems(Update, apply_settings);
+    }
+}
+
+// Systems ---------------------------------------------------------------------
+
+fn apply_settings(
+    settings: Res<Settings>,
+    mut applied_window: Local<Option<WindowSettings>>,
+    mut chunk_config: ResMut<ChunkConfig>,
+    mut global_volume: ResMut<GlobalVolume>,
+    mut window_query: Query<&mut Window, With<PrimaryWindow>>,
+    mut light_query: Query<&mut DirectionalLight, With<SunMoonLight>>,
+) {
+    if !settings.is_changed() {
+        return;
+    }
+    // The file doesn't have to be written back when it was just read
+    if !settings.is_added() {
+        settings.save();
+    }
+
+    // Changing anything else must not undo a manual resize or move of the
+    // window
+    let window_settings = settings.window_settings();
+    if applied_window.as_ref() != Some(&window_settings) {
+        for mut window in window_query.iter_mut() {
+            settings.apply_window(&mut window);
+        }
+        *applied_window = Some(window_settings);
+    }
+    for mut light in light_query.iter_mut() {
+        light.shadows_enabled = settings.shadows;
+    }
+    chunk_config.view_radius = settings.view_distance.max(1);
+    global_volume.volume = Volume::new(settings.volume.clamp(0.0, 1.0));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mode_override_is_not_saved() {
+        let settings = Settings {
+            mode_override: Some(DisplayMode::Fullscreen),
+            ..default()
+        };
+        assert_eq!(settings.display_mode(), DisplayMode::Fullscreen);
+
+        let content = ron::ser::to_string(&settings).unwrap();
+        let saved = ron::from_str::<Settings>(&content).unwrap();
+        assert_eq!(saved.mode_override, None);
+        assert_eq!(saved.display_mode(), DisplayMode::Windowed);
+    }
+}
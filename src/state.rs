@@ -0,0 +1,67 @@
+use bevy::prelude::*;
+
+use crate::{
+    input::{Action, Actions},
+    menu::{is_rebinding, MenuSet},
+};
+
+// States ----------------------------------------------------------------------
+
+#[derive(States, Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum AppState {
+    #[default]
+    MainMenu,
+    // Waiting for the entity definitions and their assets
+    Loading,
+    InGame,
+    Paused,
+}
+
+// Plugin ----------------------------------------------------------------------
+
+pub struct StatePlugin;
+
+impl Plugin for StatePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_state::<AppState>()
+            .add_systems(
+                Update,
+                // The pause input may be the one being bound on the pause
+                // menu, which is checked before the menu captures it
+                toggle_pause.before(MenuSet).run_if(
+                    (in_state(AppState::InGame)
+                        .or_else(in_state(AppState::Paused)))
+                    .and_then(not(is_rebinding)),
+                ),
+            )
+            .add_systems(OnEnter(AppState::Paused), pause_time)
+            .add_systems(OnExit(AppState::Paused), resume_time);
+    }
+}
+
+// Systems ---------------------------------------------------------------------
+
+fn toggle_pause(
+    actions: Res<Actions>,
+    state: Res<State<AppState>>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    if !actions.just_pressed(Action::Pause) {
+        return;
+    }
+    match state.get() {
+        AppState::InGame => next_state.set(AppState::Paused),
+        AppState::Paused => next_state.set(AppState::InGame),
+        _ => {}
+    }
+}
+
+// Freezing the virtual clock also stops animations and anything driven by the
+// frame delta
+fn pause_time(mut time: ResMut<Time<Virtual>>) {
+    time.pause();
+}
+
+fn resume_time(mut time: ResMut<Time<Virtual>>) {
+    time.unpause();
+}
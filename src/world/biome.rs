@@ -0,0 +1,176 @@
+use bevy::prelude::*;
+use noise::{NoiseFn, Perlin};
+use rand::Rng;
+use serde::Deserialize;
+
+use super::WorldSeed;
+use crate::entities::entity::{
+    block, vegetation, EntitiesReloaded, Resources as EntityResources,
+};
+
+// Frequency of the climate noises, biomes are much wider than hills
+const CLIMATE_FREQUENCY: f64 = 0.008;
+
+// Width of the transition between two biomes in the climate space, higher
+// values produce wider (and more mixed) borders
+const BLEND: f64 = 0.08;
+
+// Temperature lost for each block above the sea level
+const TEMPERATURE_LAPSE: f64 = 0.015;
+
+// Core ------------------------------------------------------------------------
+
+fn default_max_height() -> i32 {
+    1
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct VegetationData {
+    pub variant: vegetation::Variant,
+    // Probability of a column of the biome getting this plant
+    pub chance: f64,
+    // Plants are stacked from 1 up to `max_height` entities
+    #[serde(default = "default_max_height")]
+    pub max_height: i32,
+    // Block placed under the plant instead of the biome surface
+    #[serde(default)]
+    pub ground: Option<block::Variant>,
+}
+
+// Declared in the entity definition files, next to the blocks and plants it
+// uses
+#[derive(Clone, Debug, Deserialize)]
+pub struct BiomeData {
+    pub name: String,
+    // Climate (-1.0 to 1.0) at which the biome is the most common
+    pub temperature: f64,
+    pub humidity: f64,
+    pub surface: block::Variant,
+    pub soil: block::Variant,
+    #[serde(default)]
+    pub vegetation: Vec<VegetationData>,
+}
+
+impl BiomeData {
+    // Picks (at most) one plant for a column, using a single roll so every
+    // column consumes the same amount of randomness
+    pub fn pick_vegetation(
+        &self,
+        rng: &mut impl Rng,
+    ) -> Option<&VegetationData> {
+        let roll = rng.gen::<f64>();
+        let mut accumulated = 0.0;
+        for data in self.vegetation.iter() {
+            accumulated += data.chance;
+            if roll < accumulated {
+                return Some(data);
+            }
+        }
+        None
+    }
+}
+
+// Resources -------------------------------------------------------------------
+
+#[derive(Resource, Clone)]
+pub struct BiomeMap {
+    temperature_noise: Perlin,
+    humidity_noise: Perlin,
+    biomes: Vec<BiomeData>,
+}
+
+impl BiomeMap {
+    pub fn new(seed: WorldSeed, biomes: Vec<BiomeData>) -> BiomeMap {
+        let noise_seed = (seed.0 ^ (seed.0 >> 32)) as u32;
+        BiomeMap {
+            temperature_noise: Perlin::new(noise_seed.wrapping_add(2)),
+            humidity_noise: Perlin::new(noise_seed.wrapping_add(3)),
+            biomes,
+        }
+    }
+
+    // Temperature and humidity of the column at (x, z), higher columns are
+    // colder
+    pub fn climate(&self, x: i32, z: i32, height_above_sea: i32) -> (f64, f64) {
+        let point =
+            [x as f64 * CLIMATE_FREQUENCY, z as f64 * CLIMATE_FREQUENCY];
+        let temperature = self.temperature_noise.get(point)
+            - height_above_sea.max(0) as f64 * TEMPERATURE_LAPSE;
+        let humidity = self.humidity_noise.get(point);
+        (temperature, humidity)
+    }
+
+    // Influence of each biome over the column (summing up to 1.0), based on
+    // how close the column climate is to the climate of each biome
+    pub fn weights(&self, x: i32, z: i32, height_above_sea: i32) -> Vec<f64> {
+        let (temperature, humidity) = self.climate(x, z, height_above_sea);
+
+        let mut weights = self
+            .biomes
+            .iter()
+            .map(|data| {
+                let distance = (temperature - data.temperature).powi(2)
+                    + (humidity - data.humidity).powi(2);
+                (-distance / (2.0 * BLEND * BLEND)).exp()
+            })
+            .collect::<Vec<_>>();
+
+        let total = weights.iter().sum::<f64>();
+        let count = weights.len() as f64;
+        for weight in weights.iter_mut() {
+            *weight = if total > 0.0 {
+                *weight / total
+            } else {
+                1.0 / count
+            };
+        }
+        weights
+    }
+
+    // Biome of a column, sampled from the weights so columns close to a
+    // border are scattered between both biomes instead of forming a hard edge.
+    // `None` when no biome is defined.
+    pub fn sample(
+        &self,
+        x: i32,
+        z: i32,
+        height_above_sea: i32,
+        rng: &mut impl Rng,
+    ) -> Option<&BiomeData> {
+        let weights = self.weights(x, z, height_above_sea);
+        let roll = rng.gen::<f64>();
+        let mut accumulated = 0.0;
+        for (biome, weight) in self.biomes.iter().zip(weights) {
+            accumulated += weight;
+            if roll < accumulated {
+                return Some(biome);
+            }
+        }
+        self.biomes.last()
+    }
+}
+
+// Systems ---------------------------------------------------------------------
+
+// The biomes are only known once the definition files are loaded
+pub fn setup_biomes(
+    mut commands: Commands,
+    seed: Res<WorldSeed>,
+    resources: Res<EntityResources>,
+) {
+    if resources.biomes().is_empty() {
+        warn!("No biome is defined, the world will be empty");
+    }
+    commands.insert_resource(BiomeMap::new(*seed, resources.biomes().to_vec()));
+}
+
+// New chunks use the biomes of the definition files reloaded from disk
+pub fn reload_biomes(
+    mut events: EventReader<EntitiesReloaded>,
+    resources: Res<EntityResources>,
+    mut biome_map: ResMut<BiomeMap>,
+) {
+    if events.read().last().is_some() {
+        biome_map.biomes = resources.biomes().to_vec();
+    }
+}
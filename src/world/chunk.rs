@@ -0,0 +1,337 @@
+use bevy::{ecs::system::SystemParam, prelude::*};
+use std::collections::{HashMap, HashSet};
+
+use super::{
+    mesh::{build_chunk_meshes, BlockAtlas},
+    BlockChanged, Generator, VoxelWorld,
+};
+use crate::{
+    entities::entity::{vegetation, Resources as EntityResources},
+    player::Player,
+};
+
+// Width (in blocks) of the square column of the world stored by each chunk
+pub const CHUNK_SIZE: i32 = 16;
+
+// Chunks generated on a single frame, so walking into new terrain spreads the
+// work over a few frames instead of freezing the game
+const MAX_CHUNKS_PER_FRAME: usize = 2;
+
+// Components ------------------------------------------------------------------
+
+#[derive(Component, Debug)]
+pub struct Chunk;
+
+#[derive(Component, Debug)]
+pub struct ChunkMesh;
+
+// Resources -------------------------------------------------------------------
+
+#[derive(Resource, Clone, Debug)]
+pub struct ChunkConfig {
+    // Distance (in chunks) around the player chunk that is kept loaded
+    pub view_radius: i32,
+}
+
+impl Default for ChunkConfig {
+    fn default() -> Self {
+        ChunkConfig { view_radius: 2 }
+    }
+}
+
+pub struct LoadedChunk {
+    pub entity: Entity,
+    // Entities holding the merged meshes of the blocks of the chunk
+    pub meshes: Vec<Entity>,
+}
+
+#[derive(Resource, Default)]
+pub struct LoadedChunks {
+    pub chunks: HashMap<IVec2, LoadedChunk>,
+}
+
+// Blocks left out of the chunk meshes (without changing the world), so that
+// they don't hide the player
+#[derive(Resource, Default, Debug)]
+pub struct CutAway {
+    pub blocks: HashSet<IVec3>,
+}
+
+// Helpers ---------------------------------------------------------------------
+
+pub fn chunk_coordinates(coordinates: IVec3) -> IVec2 {
+    IVec2::new(
+        coordinates.x.div_euclid(CHUNK_SIZE),
+        coordinates.z.div_euclid(CHUNK_SIZE),
+    )
+}
+
+// Every chunk within `radius` of `center`, sorted from the nearest to the
+// farthest one
+pub fn chunks_around(center: IVec2, radius: i32) -> Vec<IVec2> {
+    let mut chunks = Vec::new();
+    for x in -radius..=radius {
+        for z in -radius..=radius {
+            let offset = IVec2::new(x, z);
+            if offset.length_squared() <= radius * radius {
+                chunks.push(center + offset);
+            }
+        }
+    }
+    chunks.sort_by_key(|chunk| (*chunk - center).length_squared());
+    chunks
+}
+
+#[derive(SystemParam)]
+pub struct ChunkAssets<'w> {
+    resources: Res<'w, EntityResources>,
+    atlas: Option<Res<'w, BlockAtlas>>,
+    cut_away: Res<'w, CutAway>,
+    meshes: ResMut<'w, Assets<Mesh>>,
+}
+
+impl ChunkAssets<'_> {
+    fn spawn_meshes(
+        &mut self,
+        commands: &mut Commands,
+        voxel_world: &VoxelWorld,
+        coordinates: IVec2,
+    ) -> Vec<Entity> {
+        let (Some(atlas), Some(data)) =
+            (self.atlas.as_ref(), voxel_world.chunk(coordinates))
+        else {
+            return Vec::new();
+        };
+
+        build_chunk_meshes(data, voxel_world, atlas, &self.cut_away.blocks)
+            .into_iter()
+            .map(|(mesh, material)| {
+                commands
+                    .spawn((
+                        PbrBundle {
+                            mesh: self.meshes.add(mesh),
+                            material,
+                            ..default()
+                        },
+                        ChunkMesh,
+                    ))
+                    .id()
+            })
+            .collect()
+    }
+
+    fn respawn_meshes(
+        &mut self,
+        commands: &mut Commands,
+        voxel_world: &VoxelWorld,
+        coordinates: IVec2,
+        chunk: &mut LoadedChunk,
+    ) {
+        for entity in chunk.meshes.drain(..) {
+            commands.entity(entity).despawn_recursive();
+        }
+        chunk.meshes = self.spawn_meshes(commands, voxel_world, coordinates);
+        commands.entity(chunk.entity).push_children(&chunk.meshes);
+    }
+
+    fn spawn_chunk(
+        &mut self,
+        commands: &mut Commands,
+        voxel_world: &VoxelWorld,
+        coordinates: IVec2,
+    ) -> LoadedChunk {
+        let meshes = self.spawn_meshes(commands, voxel_world, coordinates);
+
+        let mut children = meshes.clone();
+        if let Some(data) = voxel_world.chunk(coordinates) {
+            for (coordinates, variant) in data.vegetation.iter() {
+                children.extend(vegetation::spawn(
+                    commands,
+                    &self.resources,
+                    vegetation::Entity {
+                        coordinates: *coordinates,
+                        variant: *variant,
+                    },
+                ));
+            }
+        }
+
+        let entity = commands
+            .spawn((SpatialBundle::default(), Chunk))
+            .push_children(&children)
+            .id();
+
+        LoadedChunk { entity, meshes }
+    }
+}
+
+// Systems ---------------------------------------------------------------------
+
+pub fn stream_chunks(
+    mut commands: Commands,
+    mut loaded_chunks: ResMut<LoadedChunks>,
+    mut voxel_world: ResMut<VoxelWorld>,
+    mut assets: ChunkAssets,
+    config: Res<ChunkConfig>,
+    generator: Generator,
+    player_query: Query<&Transform, With<Player>>,
+) {
+    // Nothing can be meshed until the block models are loaded
+    if assets.atlas.is_none() {
+        return;
+    }
+    let Ok(player_transform) = player_query.get_single() else {
+        return;
+    };
+
+    let center =
+        chunk_coordinates(player_transform.translation.round().as_ivec3());
+    let required = chunks_around(center, config.view_radius);
+
+    // Unload the chunks that are no longer in the view radius
+    loaded_chunks.chunks.retain(|coordinates, chunk| {
+        if required.contains(coordinates) {
+            return true;
+        }
+        commands.entity(chunk.entity).despawn_recursive();
+        voxel_world.unload_chunk(*coordinates);
+        false
+    });
+
+    // Load the missing ones, nearest first
+    let missing = required
+        .into_iter()
+        .filter(|coordinates| !loaded_chunks.chunks.contains_key(coordinates))
+        .take(MAX_CHUNKS_PER_FRAME)
+        .collect::<Vec<_>>();
+
+    let mut outdated = HashSet::new();
+    for coordinates in missing {
+        // Chunks that were modified are still in memory, the others are
+        // generated again
+        if !voxel_world.contains_chunk(coordinates) {
+            voxel_world
+                .insert_chunk(coordinates, generator.generate(coordinates));
+        }
+
+        let chunk =
+            assets.spawn_chunk(&mut commands, &voxel_world, coordinates);
+        loaded_chunks.chunks.insert(coordinates, chunk);
+
+        // The neighbors were meshed without this chunk, their faces against
+        // it are hidden now
+        outdated.remove(&coordinates);
+        for offset in [IVec2::X, IVec2::NEG_X, IVec2::Y, IVec2::NEG_Y] {
+            outdated.insert(coordinates + offset);
+        }
+    }
+
+    for coordinates in outdated {
+        let Some(chunk) = loaded_chunks.chunks.get_mut(&coordinates) else {
+            continue;
+        };
+
+        assets.respawn_meshes(&mut commands, &voxel_world, coordinates, chunk);
+    }
+}
+
+// Back on the main menu, the chunks of the world that was left are despawned
+// (the next world replaces the voxel world)
+pub fn unload_world(
+    mut commands: Commands,
+    mut loaded_chunks: ResMut<LoadedChunks>,
+    mut cut_away: ResMut<CutAway>,
+) {
+    for (_, chunk) in loaded_chunks.chunks.drain() {
+        commands.entity(chunk.entity).despawn_recursive();
+    }
+    cut_away.blocks.clear();
+}
+
+// Keeps the chunk meshes in sync with the voxel world
+pub fn mirror_changes(
+    mut commands: Commands,
+    mut loaded_chunks: ResMut<LoadedChunks>,
+    mut events: EventReader<BlockChanged>,
+    mut assets: ChunkAssets,
+    voxel_world: Res<VoxelWorld>,
+) {
+    let mut outdated = HashSet::new();
+    for event in events.read() {
+        outdated.insert(chunk_coordinates(event.coordinates));
+
+        // A block on the border of a chunk also hides faces of the neighbor
+        // chunk, unless it was only replaced by another block
+        if event.previous.is_some() != event.current.is_some() {
+            for (neighbor, _) in voxel_world.neighbors(event.coordinates) {
+                outdated.insert(chunk_coordinates(neighbor));
+            }
+        }
+    }
+
+    for coordinates in outdated {
+        let Some(chunk) = loaded_chunks.chunks.get_mut(&coordinates) else {
+            continue;
+        };
+
+        assets.respawn_meshes(&mut commands, &voxel_world, coordinates, chunk);
+    }
+}
+
+// Meshes every loaded chunk again when the atlas is rebuilt
+pub fn remesh_chunks(
+    mut commands: Commands,
+    mut loaded_chunks: ResMut<LoadedChunks>,
+    mut assets: ChunkAssets,
+    voxel_world: Res<VoxelWorld>,
+) {
+    if !assets
+        .atlas
+        .as_ref()
+        .is_some_and(|atlas| atlas.is_changed())
+    {
+        return;
+    }
+
+    for (coordinates, chunk) in loaded_chunks.chunks.iter_mut() {
+        assets.respawn_meshes(&mut commands, &voxel_world, *coordinates, chunk);
+    }
+}
+
+// Meshes again the chunks where blocks were cut away or put back, a few of
+// them per frame
+pub fn mirror_cut_away(
+    mut commands: Commands,
+    mut loaded_chunks: ResMut<LoadedChunks>,
+    mut assets: ChunkAssets,
+    mut previous: Local<HashSet<IVec3>>,
+    mut outdated: Local<HashSet<IVec2>>,
+    voxel_world: Res<VoxelWorld>,
+) {
+    if assets.cut_away.is_changed() {
+        // The faces of the neighbors of those blocks are shown or hidden too
+        let changed = assets.cut_away.blocks.symmetric_difference(&previous);
+        for coordinates in changed {
+            for offset in
+                [IVec3::ZERO, IVec3::X, IVec3::NEG_X, IVec3::Z, IVec3::NEG_Z]
+            {
+                outdated.insert(chunk_coordinates(*coordinates + offset));
+            }
+        }
+        previous.clone_from(&assets.cut_away.blocks);
+    }
+
+    let remeshed = outdated
+        .iter()
+        .take(MAX_CHUNKS_PER_FRAME)
+        .copied()
+        .collect::<Vec<_>>();
+    for coordinates in remeshed {
+        outdated.remove(&coordinates);
+        let Some(chunk) = loaded_chunks.chunks.get_mut(&coordinates) else {
+            continue;
+        };
+
+        assets.respawn_meshes(&mut commands, &voxel_world, coordinates, chunk);
+    }
+}
@@ -0,0 +1,383 @@
+use bevy::{
+    asset::LoadState,
+    prelude::*,
+    render::{
+        mesh::{Indices, PrimitiveTopology, VertexAttributeValues},
+        render_asset::RenderAssetUsages,
+    },
+};
+use std::collections::{HashMap, HashSet};
+
+use super::{voxel::NEIGHBORS, ChunkData, VoxelWorld};
+use crate::entities::entity::{
+    block, EntitiesReloaded, Resources as EntityResources,
+};
+
+// Atlas -----------------------------------------------------------------------
+
+// Block models are low poly cubes painted with a color palette: each face is
+// split in horizontal bands and every band samples a single texel of the
+// palette. The atlas keeps, for each face of each block, those bands so the
+// chunk mesh can be painted exactly like the original models.
+#[derive(Clone, Debug)]
+struct Band {
+    // Vertical range of the band inside the face (0.0 = bottom, 1.0 = top)
+    from: f32,
+    to: f32,
+    uv: [f32; 2],
+}
+
+#[derive(Clone, Debug)]
+struct BlockTexture {
+    material: Handle<StandardMaterial>,
+    // Bands of each face, in the same order as `NEIGHBORS`
+    faces: [Vec<Band>; 6],
+}
+
+#[derive(Resource, Default)]
+pub struct BlockAtlas {
+    textures: HashMap<block::Variant, BlockTexture>,
+}
+
+fn face_index(normal: Vec3) -> Option<usize> {
+    NEIGHBORS
+        .iter()
+        .position(|direction| direction.as_vec3().dot(normal) > 0.9)
+}
+
+// Extracts the bands of each face from the mesh of a block model
+fn extract_texture(
+    mesh: &Mesh,
+    material: Handle<StandardMaterial>,
+) -> Option<BlockTexture> {
+    let Some(VertexAttributeValues::Float32x3(positions)) =
+        mesh.attribute(Mesh::ATTRIBUTE_POSITION)
+    else {
+        return None;
+    };
+    let Some(VertexAttributeValues::Float32x3(normals)) =
+        mesh.attribute(Mesh::ATTRIBUTE_NORMAL)
+    else {
+        return None;
+    };
+    let Some(VertexAttributeValues::Float32x2(uvs)) =
+        mesh.attribute(Mesh::ATTRIBUTE_UV_0)
+    else {
+        return None;
+    };
+    let indices = match mesh.indices() {
+        Some(indices) => indices.iter().collect::<Vec<_>>(),
+        None => (0..positions.len()).collect::<Vec<_>>(),
+    };
+
+    // Heights are normalized to the model bounds
+    let (bottom, top) = positions.iter().fold(
+        (f32::MAX, f32::MIN),
+        |(bottom, top), position| {
+            (bottom.min(position[1]), top.max(position[1]))
+        },
+    );
+    let height = (top - bottom).max(f32::EPSILON);
+
+    let mut faces: [Vec<Band>; 6] = Default::default();
+    for triangle in indices.chunks_exact(3) {
+        let Some(face) = face_index(Vec3::from(normals[triangle[0]])) else {
+            continue;
+        };
+
+        let (from, to) =
+            triangle
+                .iter()
+                .fold((f32::MAX, f32::MIN), |(from, to), index| {
+                    let y = (positions[*index][1] - bottom) / height;
+                    (from.min(y), to.max(y))
+                });
+
+        // Top and bottom faces are flat, they are painted by a single band
+        let (from, to) = if NEIGHBORS[face].y == 0 {
+            (from, to)
+        } else {
+            (0.0, 1.0)
+        };
+
+        // Both triangles of a quad share the same band
+        let bands = &mut faces[face];
+        if bands.iter().any(|band| {
+            (band.from - from).abs() < 0.01 && (band.to - to).abs() < 0.01
+        }) {
+            continue;
+        }
+        bands.push(Band {
+            from,
+            to,
+            uv: uvs[triangle[0]],
+        });
+    }
+
+    for bands in faces.iter_mut() {
+        bands.sort_by(|a, b| a.from.total_cmp(&b.from));
+    }
+
+    Some(BlockTexture { material, faces })
+}
+
+// Mesh builder ----------------------------------------------------------------
+
+#[derive(Default)]
+struct MeshBuilder {
+    positions: Vec<[f32; 3]>,
+    normals: Vec<[f32; 3]>,
+    uvs: Vec<[f32; 2]>,
+    indices: Vec<u32>,
+}
+
+impl MeshBuilder {
+    // Adds a quad from its corners, sorted counter-clockwise when looking at
+    // its front side
+    fn quad(&mut self, corners: [Vec3; 4], normal: Vec3, uv: [f32; 2]) {
+        let start = self.positions.len() as u32;
+        for corner in corners {
+            self.positions.push(corner.to_array());
+            self.normals.push(normal.to_array());
+            self.uvs.push(uv);
+        }
+        self.indices.extend_from_slice(&[
+            start,
+            start + 1,
+            start + 2,
+            start,
+            start + 2,
+            start + 3,
+        ]);
+    }
+
+    fn build(self) -> Mesh {
+        Mesh::new(
+            PrimitiveTopology::TriangleList,
+            RenderAssetUsages::RENDER_WORLD,
+        )
+        .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, self.positions)
+        .with_inserted_attribute(Mesh::ATTRIBUTE_NORMAL, self.normals)
+        .with_inserted_attribute(Mesh::ATTRIBUTE_UV_0, self.uvs)
+        .with_inserted_indices(Indices::U32(self.indices))
+    }
+}
+
+// Axes of the plane of a face: `u` is always horizontal and `v` is vertical
+// on side faces, so merged side faces keep the bands of each block
+fn face_axes(normal: IVec3) -> (IVec3, IVec3) {
+    match (normal.x, normal.y) {
+        (0, 0) => (IVec3::X, IVec3::Y),
+        (_, 0) => (IVec3::Z, IVec3::Y),
+        _ => (IVec3::X, IVec3::Z),
+    }
+}
+
+// Visible face of a block, the merged ones are `width` by `height` blocks
+struct Face {
+    coordinates: IVec3,
+    variant: block::Variant,
+    width: i32,
+    height: i32,
+}
+
+// Greedy meshing: faces of the same block variant that are next to each other
+// on the same plane are merged into a single rectangle. Side faces are only
+// merged horizontally since their bands repeat on each block.
+fn merge_faces(
+    visible: &HashMap<IVec3, block::Variant>,
+    normal: IVec3,
+) -> Vec<Face> {
+    let (u, v) = face_axes(normal);
+    let merge_vertically = normal.y != 0;
+
+    let mut coordinates = visible.keys().copied().collect::<Vec<_>>();
+    coordinates.sort_by_key(|c| (c.dot(normal), c.dot(v), c.dot(u)));
+
+    let mut merged = HashSet::new();
+    let mut faces = Vec::new();
+    for start in coordinates {
+        if merged.contains(&start) {
+            continue;
+        }
+        let variant = visible[&start];
+        let is_free = |coordinates: IVec3, merged: &HashSet<IVec3>| {
+            visible.get(&coordinates) == Some(&variant)
+                && !merged.contains(&coordinates)
+        };
+
+        let mut width = 1;
+        while is_free(start + u * width, &merged) {
+            width += 1;
+        }
+
+        let mut height = 1;
+        while merge_vertically
+            && (0..width).all(|i| is_free(start + u * i + v * height, &merged))
+        {
+            height += 1;
+        }
+
+        for i in 0..width {
+            for j in 0..height {
+                merged.insert(start + u * i + v * j);
+            }
+        }
+        faces.push(Face {
+            coordinates: start,
+            variant,
+            width,
+            height,
+        });
+    }
+    faces
+}
+
+fn add_face(
+    builder: &mut MeshBuilder,
+    face: &Face,
+    normal: IVec3,
+    bands: &[Band],
+) {
+    let (u, v) = face_axes(normal);
+    let (u, v, normal) = (u.as_vec3(), v.as_vec3(), normal.as_vec3());
+
+    // Blocks are unit cubes centered on their coordinates
+    let origin = face.coordinates.as_vec3() + normal * 0.5 - (u + v) * 0.5;
+    let width = u * face.width as f32;
+    let flipped = u.cross(v).dot(normal) < 0.0;
+
+    let is_flat = normal.y != 0.0;
+    for row in 0..face.height {
+        for band in bands.iter() {
+            // Flat faces have a single band spanning every merged row
+            let (from, to) = if is_flat {
+                (0.0, face.height as f32)
+            } else {
+                (row as f32 + band.from, row as f32 + band.to)
+            };
+            let bottom = origin + v * from;
+            let top = origin + v * to;
+
+            let corners = if flipped {
+                [bottom, top, top + width, bottom + width]
+            } else {
+                [bottom, bottom + width, top + width, top]
+            };
+            builder.quad(corners, normal, band.uv);
+
+            if is_flat {
+                return;
+            }
+        }
+    }
+}
+
+// Builds the meshes of a chunk, one per material used by its blocks. Faces
+// hidden by a neighbor block are skipped, blocks in `cut_away` are treated as
+// air.
+pub fn build_chunk_meshes(
+    chunk: &ChunkData,
+    voxel_world: &VoxelWorld,
+    atlas: &BlockAtlas,
+    cut_away: &HashSet<IVec3>,
+) -> Vec<(Mesh, Handle<StandardMaterial>)> {
+    let mut builders = HashMap::<Handle<StandardMaterial>, MeshBuilder>::new();
+
+    for (face, normal) in NEIGHBORS.iter().enumerate() {
+        let visible = chunk
+            .blocks
+            .iter()
+            .filter(|(coordinates, _)| {
+                let neighbor = **coordinates + *normal;
+                !cut_away.contains(*coordinates)
+                    && (!voxel_world.is_solid(neighbor)
+                        || cut_away.contains(&neighbor))
+            })
+            .map(|(coordinates, variant)| (*coordinates, *variant))
+            .collect::<HashMap<_, _>>();
+
+        for merged in merge_faces(&visible, *normal) {
+            let Some(texture) = atlas.textures.get(&merged.variant) else {
+                continue;
+            };
+            let builder = builders.entry(texture.material.clone()).or_default();
+            add_face(builder, &merged, *normal, &texture.faces[face]);
+        }
+    }
+
+    builders
+        .into_iter()
+        .filter(|(_, builder)| !builder.indices.is_empty())
+        .map(|(material, builder)| (builder.build(), material))
+        .collect()
+}
+
+// Systems ---------------------------------------------------------------------
+
+// Builds the atlas once the blocks are registered and their models loaded
+pub fn build_atlas(
+    mut commands: Commands,
+    resources: Res<EntityResources>,
+    asset_server: Res<AssetServer>,
+    meshes: Res<Assets<Mesh>>,
+    atlas: Option<Res<BlockAtlas>>,
+) {
+    if atlas.is_some() {
+        return;
+    }
+
+    if !resources.is_ready() {
+        return;
+    }
+
+    let mut textures = HashMap::new();
+    for variant in block::variants(&resources) {
+        let (Some(mesh), Some(material)) = (
+            block::get_mesh(&variant, &resources),
+            block::get_material(&variant, &resources),
+        ) else {
+            continue;
+        };
+        // Blocks whose model failed to load are left out of the atlas (and
+        // so are invisible) instead of blocking the whole world
+        if asset_server.load_state(&mesh) == LoadState::Failed {
+            continue;
+        }
+        let Some(mesh) = meshes.get(mesh) else {
+            return;
+        };
+        match extract_texture(mesh, material) {
+            Some(texture) => {
+                textures.insert(variant, texture);
+            }
+            None => error!("Unable to read the model of block {:?}", variant),
+        }
+    }
+
+    commands.insert_resource(BlockAtlas { textures });
+}
+
+// Drops the atlas when the blocks are redefined or one of their meshes changes
+// on disk, so it's built again (and the chunks meshed again) with the new data
+pub fn invalidate_atlas(
+    mut commands: Commands,
+    mut reloaded: EventReader<EntitiesReloaded>,
+    mut mesh_events: EventReader<AssetEvent<Mesh>>,
+    resources: Res<EntityResources>,
+) {
+    let mut outdated = reloaded.read().last().is_some();
+    for event in mesh_events.read() {
+        let AssetEvent::Modified { id } = event else {
+            continue;
+        };
+        outdated |= block::variants(&resources).any(|variant| {
+            block::get_mesh(&variant, &resources)
+                .is_some_and(|mesh| mesh.id() == *id)
+        });
+    }
+
+    if outdated {
+        commands.remove_resource::<BlockAtlas>();
+    }
+}
@@ -0,0 +1,261 @@
+use bevy::{ecs::system::SystemParam, prelude::*};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+mod biome;
+mod chunk;
+mod mesh;
+mod terrain;
+mod voxel;
+
+pub use biome::{BiomeData, BiomeMap};
+pub use chunk::{ChunkConfig, CutAway, CHUNK_SIZE};
+pub use terrain::{Terrain, TerrainConfig};
+pub use voxel::{BlockChanged, ChunkData, RaycastHit, VoxelWorld};
+
+use crate::state::AppState;
+
+// Resources -------------------------------------------------------------------
+
+#[derive(Resource, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WorldSeed(pub u64);
+
+impl Default for WorldSeed {
+    fn default() -> Self {
+        WorldSeed(rand::random())
+    }
+}
+
+impl WorldSeed {
+    // Numeric seeds are used as they are, any other text is hashed (FNV-1a,
+    // which unlike the std hasher is stable between builds) so that
+    // "my world" is as valid a seed as "42"
+    pub fn parse(value: &str) -> WorldSeed {
+        let value = value.trim();
+        match value.parse::<u64>() {
+            Ok(seed) => WorldSeed(seed),
+            Err(_) => WorldSeed(value.bytes().fold(
+                0xcbf2_9ce4_8422_2325,
+                |hash, byte| {
+                    (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+                },
+            )),
+        }
+    }
+
+    // Reads the seed from the `WORLD_SEED` environment variable, falling back
+    // to a random seed (the `--seed` argument is handled by the CLI)
+    pub fn from_env() -> WorldSeed {
+        match std::env::var("WORLD_SEED") {
+            Ok(value) => WorldSeed::parse(&value),
+            Err(_) => WorldSeed::default(),
+        }
+    }
+
+    // Each chunk gets its own RNG derived from the world seed, so its content
+    // doesn't depend on the order in which chunks are generated
+    pub fn chunk_rng(&self, chunk: IVec2) -> StdRng {
+        let mut hash = self.0;
+        for value in [chunk.x, chunk.y] {
+            // SplitMix64 step
+            hash = (hash ^ value as u32 as u64)
+                .wrapping_add(0x9e37_79b9_7f4a_7c15);
+            hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+            hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+            hash ^= hash >> 31;
+        }
+        StdRng::seed_from_u64(hash)
+    }
+}
+
+// Plugin ----------------------------------------------------------------------
+
+pub struct WorldPlugin;
+
+impl Plugin for WorldPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<WorldSeed>()
+            .init_resource::<TerrainConfig>()
+            .init_resource::<ChunkConfig>()
+            .init_resource::<chunk::LoadedChunks>()
+            .init_resource::<CutAway>()
+            .init_resource::<VoxelWorld>()
+            .add_event::<BlockChanged>()
+            .add_systems(OnEnter(AppState::MainMenu), chunk::unload_world)
+            .add_systems(OnEnter(AppState::Loading), terrain::setup_terrain)
+            .add_systems(OnExit(AppState::Loading), biome::setup_biomes)
+            .add_systems(
+                Update,
+                (
+                    (mesh::invalidate_atlas, mesh::build_atlas).chain(),
+                    (
+                        biome::reload_biomes,
+                        chunk::remesh_chunks,
+                        chunk::stream_chunks,
+                        voxel::send_changes,
+                        chunk::mirror_changes,
+                        chunk::mirror_cut_away,
+                    )
+                        .chain()
+                        .run_if(in_state(AppState::InGame)),
+                )
+                    .chain(),
+            );
+    }
+}
+
+// Generation ------------------------------------------------------------------
+
+// Everything needed to generate the layout of a chunk
+#[derive(SystemParam)]
+pub struct Generator<'w> {
+    pub seed: Res<'w, WorldSeed>,
+    pub terrain: Res<'w, Terrain>,
+    pub biomes: Res<'w, BiomeMap>,
+}
+
+impl Generator<'_> {
+    pub fn generate(&self, chunk: IVec2) -> ChunkData {
+        generate(*self.seed, &self.terrain, &self.biomes, chunk)
+    }
+}
+
+// Every random decision of a chunk is taken from its own RNG, seeded by the
+// world seed and the chunk coordinates, and consumed in a fixed order, so the
+// same seed always yields the same layout
+pub fn generate(
+    seed: WorldSeed,
+    terrain: &Terrain,
+    biomes: &BiomeMap,
+    chunk: IVec2,
+) -> ChunkData {
+    let mut rng = seed.chunk_rng(chunk);
+    let mut data = ChunkData::default();
+
+    let origin = chunk * CHUNK_SIZE;
+    for x in origin.x..origin.x + CHUNK_SIZE {
+        for z in origin.y..origin.y + CHUNK_SIZE {
+            let height = terrain.height(x, z);
+            let Some(biome) =
+                biomes.sample(x, z, height - terrain.sea_level(), &mut rng)
+            else {
+                continue;
+            };
+
+            // Soil under the surface
+            for y in terrain.bottom(x, z)..height {
+                data.blocks.insert(IVec3 { x, y, z }, biome.soil);
+            }
+
+            // Surface and vegetation on top of it
+            let surface = IVec3 { x, y: height, z };
+            let plant = if terrain.is_underwater(height) {
+                None
+            } else {
+                biome.pick_vegetation(&mut rng)
+            };
+
+            let variant = match plant {
+                Some(plant) => plant.ground.unwrap_or(biome.surface),
+                None if terrain.is_underwater(height) => biome.soil,
+                None => biome.surface,
+            };
+            data.blocks.insert(surface, variant);
+
+            if let Some(plant) = plant {
+                for y in 1..=rng.gen_range(1..=plant.max_height) {
+                    data.vegetation
+                        .insert(surface + IVec3::Y * y, plant.variant);
+                }
+            }
+        }
+    }
+
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entities::{entity::block, EntityDefinitions, Id};
+
+    fn generate_with(
+        seed: WorldSeed,
+        config: TerrainConfig,
+        chunk: IVec2,
+    ) -> ChunkData {
+        let definitions = ron::from_str::<EntityDefinitions>(include_str!(
+            "../../assets/entities/biomes.entities.ron"
+        ))
+        .unwrap();
+        let terrain = Terrain::new(seed, config);
+        let biomes = BiomeMap::new(seed, definitions.biomes);
+        generate(seed, &terrain, &biomes, chunk)
+    }
+
+    fn generate_with_seed(seed: WorldSeed, chunk: IVec2) -> ChunkData {
+        generate_with(seed, TerrainConfig::default(), chunk)
+    }
+
+    #[test]
+    fn same_seed_generates_the_same_chunk() {
+        for chunk in [IVec2::ZERO, IVec2::new(3, -2)] {
+            let first = generate_with_seed(WorldSeed(42), chunk);
+            let second = generate_with_seed(WorldSeed(42), chunk);
+            assert!(!first.blocks.is_empty());
+            assert_eq!(first.blocks, second.blocks);
+            assert_eq!(first.vegetation, second.vegetation);
+        }
+    }
+
+    #[test]
+    fn different_seeds_generate_different_chunks() {
+        let first = generate_with_seed(WorldSeed(1), IVec2::ZERO);
+        let second = generate_with_seed(WorldSeed(2), IVec2::ZERO);
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn flat_columns_have_soil_under_the_grass() {
+        let seed = WorldSeed(42);
+        let config = TerrainConfig {
+            soil_depth: 3,
+            ..default()
+        };
+        let terrain = Terrain::new(seed, config.clone());
+        let data = generate_with(seed, config.clone(), IVec2::ZERO);
+        let grass = block::Variant(Id::new("grass"));
+        let dirt = block::Variant(Id::new("dirt"));
+
+        let mut checked = 0;
+        for x in 1..CHUNK_SIZE - 1 {
+            for z in 1..CHUNK_SIZE - 1 {
+                let height = terrain.height(x, z);
+                let surface = IVec3::new(x, height, z);
+                if terrain.bottom(x, z) != height - config.soil_depth
+                    || data.blocks.get(&surface) != Some(&grass)
+                {
+                    continue;
+                }
+
+                for depth in 1..=config.soil_depth {
+                    let cell = surface - IVec3::Y * depth;
+                    assert_eq!(data.blocks.get(&cell), Some(&dirt));
+                }
+                let below = surface - IVec3::Y * (config.soil_depth + 1);
+                assert_eq!(data.blocks.get(&below), None);
+                checked += 1;
+            }
+        }
+        assert!(checked > 0);
+    }
+
+    #[test]
+    fn text_seeds_are_stable() {
+        // FNV-1a of "foo", the value must not change between builds
+        assert_eq!(WorldSeed::parse("foo"), WorldSeed(0xdcb2_7518_fed9_d577));
+        assert_eq!(WorldSeed::parse("foo"), WorldSeed::parse("foo"));
+        assert_eq!(WorldSeed::parse(" foo "), WorldSeed::parse("foo"));
+        assert_ne!(WorldSeed::parse("foo"), WorldSeed::parse("bar"));
+        assert_eq!(WorldSeed::parse("42"), WorldSeed(42));
+    }
+}
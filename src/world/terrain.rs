@@ -0,0 +1,123 @@
+use bevy::prelude::*;
+use noise::{Fbm, MultiFractal, NoiseFn, Perlin};
+
+use super::WorldSeed;
+
+// Resources -------------------------------------------------------------------
+
+#[derive(Resource, Clone, Debug)]
+pub struct TerrainConfig {
+    // Maximum distance (in blocks) between the surface and the sea level
+    pub amplitude: f64,
+    // Frequency of the first octave, lower values produce wider hills
+    pub frequency: f64,
+    pub octaves: usize,
+    // Frequency multiplier between two consecutive octaves
+    pub lacunarity: f64,
+    // Amplitude multiplier between two consecutive octaves
+    pub persistence: f64,
+    // Height around which the terrain oscillates, columns below it are
+    // treated as seabed (no grass and no vegetation)
+    pub sea_level: i32,
+    // Height of each terrace step on cliffs
+    pub cliff_height: i32,
+    // How much of the map is covered by cliffs (0.0 = none, 1.0 = everywhere)
+    pub cliff_coverage: f64,
+    // Blocks of dirt placed under the surface of flat areas
+    pub soil_depth: i32,
+}
+
+impl Default for TerrainConfig {
+    fn default() -> Self {
+        TerrainConfig {
+            amplitude: 6.0,
+            frequency: 0.03,
+            octaves: 4,
+            lacunarity: 2.0,
+            persistence: 0.5,
+            sea_level: 0,
+            cliff_height: 3,
+            cliff_coverage: 0.3,
+            soil_depth: 1,
+        }
+    }
+}
+
+#[derive(Resource, Clone)]
+pub struct Terrain {
+    config: TerrainConfig,
+    height_noise: Fbm<Perlin>,
+    cliff_noise: Perlin,
+}
+
+impl Terrain {
+    pub fn new(seed: WorldSeed, config: TerrainConfig) -> Terrain {
+        // Noise seeds are 32 bits wide, fold the world seed so both halves
+        // contribute to the result
+        let noise_seed = (seed.0 ^ (seed.0 >> 32)) as u32;
+
+        Terrain {
+            height_noise: Fbm::<Perlin>::new(noise_seed)
+                .set_octaves(config.octaves)
+                .set_frequency(config.frequency)
+                .set_lacunarity(config.lacunarity)
+                .set_persistence(config.persistence),
+            cliff_noise: Perlin::new(noise_seed.wrapping_add(1)),
+            config,
+        }
+    }
+
+    // Height of the surface block of the column at (x, z)
+    pub fn height(&self, x: i32, z: i32) -> i32 {
+        let point = [x as f64, z as f64];
+        let offset = self.height_noise.get(point) * self.config.amplitude;
+
+        // Where the cliff noise is strong enough the offset is snapped to
+        // terraces, turning smooth slopes into steps of `cliff_height` blocks
+        let frequency = self.config.frequency * 0.5;
+        let cliff = (self
+            .cliff_noise
+            .get([point[0] * frequency, point[1] * frequency])
+            + 1.0)
+            / 2.0;
+        let offset = if cliff > 1.0 - self.config.cliff_coverage {
+            let step = self.config.cliff_height.max(1) as f64;
+            (offset / step).round() * step
+        } else {
+            offset.round()
+        };
+
+        self.config.sea_level + offset as i32
+    }
+
+    pub fn sea_level(&self) -> i32 {
+        self.config.sea_level
+    }
+
+    pub fn is_underwater(&self, height: i32) -> bool {
+        height < self.config.sea_level
+    }
+
+    // Lowest block that has to be placed on the column at (x, z) so that no
+    // hole is visible from its neighbors, even next to cliffs
+    pub fn bottom(&self, x: i32, z: i32) -> i32 {
+        let height = self.height(x, z);
+        let lowest_neighbor = [(1, 0), (-1, 0), (0, 1), (0, -1)]
+            .iter()
+            .map(|(dx, dz)| self.height(x + dx, z + dz))
+            .min()
+            .unwrap_or(height);
+
+        (height - self.config.soil_depth.max(0)).min(lowest_neighbor + 1)
+    }
+}
+
+// Systems ---------------------------------------------------------------------
+
+pub fn setup_terrain(
+    mut commands: Commands,
+    seed: Res<WorldSeed>,
+    config: Res<TerrainConfig>,
+) {
+    commands.insert_resource(Terrain::new(*seed, config.clone()));
+}
@@ -0,0 +1,329 @@
+use bevy::prelude::*;
+use std::collections::{HashMap, HashSet};
+
+use super::chunk::chunk_coordinates;
+use crate::entities::entity::{block, vegetation};
+
+pub const NEIGHBORS: [IVec3; 6] = [
+    IVec3::X,
+    IVec3::NEG_X,
+    IVec3::Y,
+    IVec3::NEG_Y,
+    IVec3::Z,
+    IVec3::NEG_Z,
+];
+
+// Result of a ray cast against the voxel world
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RaycastHit {
+    pub coordinates: IVec3,
+    // Normal of the face that was hit, zero when the ray starts inside the
+    // block
+    pub normal: IVec3,
+    pub distance: f32,
+}
+
+// Events ----------------------------------------------------------------------
+
+#[derive(Event, Clone, Debug)]
+pub struct BlockChanged {
+    pub coordinates: IVec3,
+    pub previous: Option<block::Variant>,
+    pub current: Option<block::Variant>,
+}
+
+// Resources -------------------------------------------------------------------
+
+#[derive(Default, Debug, PartialEq)]
+pub struct ChunkData {
+    pub blocks: HashMap<IVec3, block::Variant>,
+    pub vegetation: HashMap<IVec3, vegetation::Variant>,
+    // Set once the chunk is edited, modified chunks are kept in memory after
+    // being unloaded since they can't be generated again
+    pub modified: bool,
+}
+
+#[derive(Resource, Default)]
+pub struct VoxelWorld {
+    chunks: HashMap<IVec2, ChunkData>,
+    changes: Vec<BlockChanged>,
+}
+
+impl VoxelWorld {
+    // Blocks ------------------------------------------------------------------
+
+    pub fn get(&self, coordinates: IVec3) -> Option<block::Variant> {
+        self.chunks
+            .get(&chunk_coordinates(coordinates))
+            .and_then(|chunk| chunk.blocks.get(&coordinates))
+            .copied()
+    }
+
+    pub fn is_solid(&self, coordinates: IVec3) -> bool {
+        self.get(coordinates).is_some()
+    }
+
+    // Places (or replaces) a block, returning the previous one. Edits on
+    // chunks that are not in memory are ignored
+    pub fn set(
+        &mut self,
+        coordinates: IVec3,
+        variant: block::Variant,
+    ) -> Option<block::Variant> {
+        self.update(coordinates, Some(variant))
+    }
+
+    pub fn remove(&mut self, coordinates: IVec3) -> Option<block::Variant> {
+        self.update(coordinates, None)
+    }
+
+    fn update(
+        &mut self,
+        coordinates: IVec3,
+        variant: Option<block::Variant>,
+    ) -> Option<block::Variant> {
+        let chunk = self.chunks.get_mut(&chunk_coordinates(coordinates))?;
+        let previous = match variant {
+            Some(variant) => chunk.blocks.insert(coordinates, variant),
+            None => chunk.blocks.remove(&coordinates),
+        };
+
+        if previous != variant {
+            chunk.modified = true;
+            self.changes.push(BlockChanged {
+                coordinates,
+                previous,
+                current: variant,
+            });
+        }
+        previous
+    }
+
+    // The six cells sharing a face with `coordinates`, in the same order as
+    // `NEIGHBORS`, with their block if any
+    pub fn neighbors(
+        &self,
+        coordinates: IVec3,
+    ) -> [(IVec3, Option<block::Variant>); 6] {
+        NEIGHBORS.map(|offset| {
+            let neighbor = coordinates + offset;
+            (neighbor, self.get(neighbor))
+        })
+    }
+
+    // Walks the grid cell by cell along the ray (Amanatides & Woo's DDA) and
+    // returns the first solid block within `max_distance`, going through the
+    // `ignored` ones
+    pub fn raycast(
+        &self,
+        origin: Vec3,
+        direction: Vec3,
+        max_distance: f32,
+        ignored: &HashSet<IVec3>,
+    ) -> Option<RaycastHit> {
+        let direction = direction.try_normalize()?;
+
+        // Blocks are centered on their coordinates, shifting the origin by
+        // half a block aligns the cells with the integer grid
+        let start = origin + Vec3::splat(0.5);
+        let mut cell = start.floor().as_ivec3();
+        let step = direction.signum().as_ivec3();
+
+        // Distance along the ray to cross a whole cell on each axis, and to
+        // reach the first boundary of the current cell
+        let delta = direction.abs().recip();
+        let mut next = Vec3::select(
+            direction.cmpgt(Vec3::ZERO),
+            (cell.as_vec3() + Vec3::ONE - start) * delta,
+            (start - cell.as_vec3()) * delta,
+        );
+        // Axes the ray is parallel to are never crossed
+        next = Vec3::select(direction.cmpeq(Vec3::ZERO), Vec3::INFINITY, next);
+
+        let mut normal = IVec3::ZERO;
+        let mut distance = 0.0;
+        while distance <= max_distance {
+            if self.is_solid(cell) && !ignored.contains(&cell) {
+                return Some(RaycastHit {
+                    coordinates: cell,
+                    normal,
+                    distance,
+                });
+            }
+
+            // Move to the nearest boundary
+            let axis = if next.x < next.y && next.x < next.z {
+                0
+            } else if next.y < next.z {
+                1
+            } else {
+                2
+            };
+            distance = next[axis];
+            next[axis] += delta[axis];
+            cell[axis] += step[axis];
+            normal = IVec3::ZERO;
+            normal[axis] = -step[axis];
+        }
+        None
+    }
+
+    // Vegetation --------------------------------------------------------------
+
+    pub fn get_vegetation(
+        &self,
+        coordinates: IVec3,
+    ) -> Option<vegetation::Variant> {
+        self.chunks
+            .get(&chunk_coordinates(coordinates))
+            .and_then(|chunk| chunk.vegetation.get(&coordinates))
+            .copied()
+    }
+
+    // Vegetation has no change event, whoever removes a plant also despawns
+    // its entity
+    pub fn remove_vegetation(
+        &mut self,
+        coordinates: IVec3,
+    ) -> Option<vegetation::Variant> {
+        let chunk = self.chunks.get_mut(&chunk_coordinates(coordinates))?;
+        let previous = chunk.vegetation.remove(&coordinates);
+        if previous.is_some() {
+            chunk.modified = true;
+        }
+        previous
+    }
+
+    // Chunks ------------------------------------------------------------------
+
+    pub fn chunk(&self, coordinates: IVec2) -> Option<&ChunkData> {
+        self.chunks.get(&coordinates)
+    }
+
+    pub fn modified_chunks(
+        &self,
+    ) -> impl Iterator<Item = (&IVec2, &ChunkData)> {
+        self.chunks.iter().filter(|(_, chunk)| chunk.modified)
+    }
+
+    pub fn contains_chunk(&self, coordinates: IVec2) -> bool {
+        self.chunks.contains_key(&coordinates)
+    }
+
+    // Whether the chunk holding the block is in memory
+    pub fn is_loaded(&self, coordinates: IVec3) -> bool {
+        self.contains_chunk(chunk_coordinates(coordinates))
+    }
+
+    // Bulk insertion used by the world generation, no change event is sent
+    pub fn insert_chunk(&mut self, coordinates: IVec2, chunk: ChunkData) {
+        self.chunks.insert(coordinates, chunk);
+    }
+
+    // Forgets the chunk unless it was modified
+    pub fn unload_chunk(&mut self, coordinates: IVec2) {
+        if let Some(chunk) = self.chunks.get(&coordinates) {
+            if !chunk.modified {
+                self.chunks.remove(&coordinates);
+            }
+        }
+    }
+}
+
+// Systems ---------------------------------------------------------------------
+
+pub fn send_changes(
+    mut voxel_world: ResMut<VoxelWorld>,
+    mut events: EventWriter<BlockChanged>,
+) {
+    if !voxel_world.changes.is_empty() {
+        events.send_batch(voxel_world.changes.drain(..));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entities::Id;
+
+    const STONE: block::Variant = block::Variant(Id::new("stone"));
+
+    fn world(blocks: &[IVec3]) -> VoxelWorld {
+        let mut world = VoxelWorld::default();
+        world.insert_chunk(IVec2::ZERO, ChunkData::default());
+        for coordinates in blocks {
+            world.set(*coordinates, STONE);
+        }
+        world
+    }
+
+    #[test]
+    fn axis_aligned_ray_hits_the_top_face() {
+        let world = world(&[IVec3::new(2, 0, 2)]);
+        let hit = world
+            .raycast(Vec3::new(2.0, 5.0, 2.0), Vec3::NEG_Y, 10.0, &default())
+            .unwrap();
+        assert_eq!(hit.coordinates, IVec3::new(2, 0, 2));
+        assert_eq!(hit.normal, IVec3::Y);
+        assert!((hit.distance - 4.5).abs() < 1e-5);
+    }
+
+    #[test]
+    fn diagonal_ray_hits_the_first_block_on_its_way() {
+        let world = world(&[IVec3::new(3, 0, 5), IVec3::new(4, 0, 5)]);
+        let hit = world
+            .raycast(
+                Vec3::new(0.2, 3.0, 5.0),
+                Vec3::new(1.0, -1.0, 0.0),
+                10.0,
+                &default(),
+            )
+            .unwrap();
+        assert_eq!(hit.coordinates, IVec3::new(3, 0, 5));
+        assert_eq!(hit.normal, IVec3::Y);
+        assert!((hit.distance - 2.5 * 2.0_f32.sqrt()).abs() < 1e-5);
+    }
+
+    #[test]
+    fn ray_parallel_to_the_ground_only_hits_the_wall() {
+        let ground = (0..8).map(|x| IVec3::new(x, 0, 1));
+        let world =
+            world(&ground.chain([IVec3::new(6, 1, 1)]).collect::<Vec<_>>());
+        let hit = world
+            .raycast(Vec3::new(0.0, 1.0, 1.0), Vec3::X, 10.0, &default())
+            .unwrap();
+        assert_eq!(hit.coordinates, IVec3::new(6, 1, 1));
+        assert_eq!(hit.normal, IVec3::NEG_X);
+        assert!((hit.distance - 5.5).abs() < 1e-5);
+    }
+
+    #[test]
+    fn ray_misses_blocks_out_of_reach() {
+        let world = world(&[IVec3::new(10, 0, 0)]);
+        let origin = Vec3::new(0.0, 0.0, 0.0);
+        assert!(world.raycast(origin, Vec3::X, 5.0, &default()).is_none());
+        assert!(world.raycast(origin, Vec3::Y, 20.0, &default()).is_none());
+        assert!(world.raycast(origin, Vec3::X, 20.0, &default()).is_some());
+    }
+
+    #[test]
+    fn ignored_blocks_are_seen_through() {
+        let world = world(&[IVec3::new(2, 0, 0), IVec3::new(4, 0, 0)]);
+        let ignored = HashSet::from([IVec3::new(2, 0, 0)]);
+        let hit = world.raycast(Vec3::ZERO, Vec3::X, 10.0, &ignored).unwrap();
+        assert_eq!(hit.coordinates, IVec3::new(4, 0, 0));
+    }
+
+    #[test]
+    fn neighbors_share_a_face_with_the_cell() {
+        let center = IVec3::new(5, 5, 5);
+        let world = world(&[center + IVec3::Y, center + IVec3::NEG_X]);
+        let neighbors = world.neighbors(center);
+
+        for ((neighbor, variant), offset) in neighbors.iter().zip(NEIGHBORS) {
+            assert_eq!(*neighbor, center + offset);
+            let expected = [IVec3::Y, IVec3::NEG_X].contains(&offset);
+            assert_eq!(variant.is_some(), expected);
+        }
+    }
+}